@@ -0,0 +1,56 @@
+//! Times lookups in a small predicate while an unrelated one grows.
+//! Run with `cargo bench`; the times should stay about the same.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use dataloglite::api::Database;
+use dataloglite::value::Value;
+
+const PEOPLE: i64 = 100;
+const LOOKUPS: u32 = 1_000;
+
+fn database(unrelated: i64) -> Database {
+    let mut db = Database::new();
+    db.insert_all((0..PEOPLE).map(|i| ("parent", i, i + 1)))
+        .unwrap();
+    db.insert_all((0..unrelated).map(|i| ("noise", i, i % 7)))
+        .unwrap();
+    db
+}
+
+// Average time of `lookup`, run `LOOKUPS` times
+fn time(mut lookup: impl FnMut(i64)) -> Duration {
+    lookup(0);
+    let start = Instant::now();
+    for i in 0..LOOKUPS {
+        lookup(i as i64 % PEOPLE);
+    }
+    start.elapsed() / LOOKUPS
+}
+
+fn main() {
+    println!(
+        "{:>10}  {:>24}  {:>24}  {:>24}",
+        "unrelated", "relations_where_first_is", "?parent(N, X)", "?parent(X, Y), parent(Y, N)"
+    );
+    for unrelated in [0, 10_000, 100_000, 1_000_000] {
+        let mut db = database(unrelated);
+        let direct = time(|i| {
+            black_box(db.relations_where_first_is("parent", &Value::Integer(i)));
+        });
+        let query = time(|i| {
+            black_box(db.query(&format!("?parent({}, X).", i)).unwrap());
+        });
+        let join = time(|i| {
+            black_box(
+                db.query(&format!("?parent(X, Y), parent(Y, {}).", i))
+                    .unwrap(),
+            );
+        });
+        println!(
+            "{:>10}  {:>24?}  {:>24?}  {:>24?}",
+            unrelated, direct, query, join
+        );
+    }
+}
@@ -1,257 +1,947 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
 
-use crate::parser::{ConjunctiveQuery, QueryProjectionFact, QueryProjectionRelation};
+use crate::convert::{self, FromRow, ToFact};
+use crate::evaluation;
+use crate::parser::{
+    check_name, parse_rule, Atom, ConjunctiveQuery, DatalogItem, NonQueryDatalogItem,
+    QueryProjection, QueryProjectionFact, QueryProjectionRelation, Rule, Term,
+    VariableBasedRelation,
+};
+use crate::query_engine::{self, Error};
+use crate::snapshot::{self, Decoder, Encoder};
+use crate::storage::{self, AtomSet, Atoms, Storage, Symbol, Symbols, Tuples};
+use crate::stratification;
+use crate::value::Value;
 
 pub struct Database {
-    facts: HashSet<crate::parser::Fact>,
-    relations: HashSet<crate::parser::Relation>,
+    // Every value of the stored atoms, which hold their symbols instead
+    symbols: Symbols,
+    // Number of symbols whose values `atoms` was given
+    stored_values: usize,
+    atoms: Box<dyn Storage>,
+    // Atoms produced by evaluating `rules`, kept apart from the base atoms
+    derived: Box<dyn Storage>,
+    // Atoms derived in the last iteration of semi-naive evaluation
+    delta: Box<dyn Storage>,
+    // Number of arguments of every predicate seen so far
+    arities: HashMap<String, usize>,
+    rules: Vec<Rule>,
+    // Indexes into `rules`, grouped by stratum, lowest first
+    strata: Vec<Vec<usize>>,
+    // Set when atoms or rules change after the last evaluation
+    stale: bool,
 }
 
-pub struct DatabaseInstance {
-    db: Database,
+/// Answers to a query: one column per named variable, in order of first
+/// appearance, and one row per distinct combination of values, sorted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Answers {
+    pub variables: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
 }
 
-impl DatabaseInstance {
-    /// Creates a new, empty Database instance
-    pub fn new() -> Self {
-        DatabaseInstance {
-            db: Database::new(),
+impl Answers {
+    pub(crate) fn new(variables: Vec<String>, rows: HashSet<Vec<Value>>) -> Self {
+        let mut rows: Vec<Vec<Value>> = rows.into_iter().collect();
+        rows.sort();
+        Answers { variables, rows }
+    }
+
+    /// Values of `variable` in every row, `None` if the query does not bind it
+    pub fn column(&self, variable: &str) -> Option<Vec<&Value>> {
+        let index = self.variables.iter().position(|v| v == variable)?;
+        Some(self.rows.iter().map(|row| &row[index]).collect())
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+/// Answers to a query, found one at a time. Each row is yielded once, in no
+/// particular order; `sorted` gives them in the order of `Answers`.
+/// Rows already yielded are kept to leave out repeated ones.
+pub struct AnswerIter<'a> {
+    variables: Vec<String>,
+    rows: Box<dyn Iterator<Item = Vec<Value>> + 'a>,
+    seen: HashSet<Vec<Value>>,
+}
+
+impl<'a> AnswerIter<'a> {
+    fn new(variables: Vec<String>, rows: impl Iterator<Item = Vec<Value>> + 'a) -> Self {
+        AnswerIter {
+            variables,
+            rows: Box::new(rows),
+            seen: HashSet::new(),
         }
     }
 
-    /// Gets a reference to the underlying database
-    pub fn get_db(&self) -> &Database {
-        &self.db
+    /// One name per column, as in `Answers`
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    /// Collects the answers not taken yet, sorted
+    pub fn sorted(mut self) -> Answers {
+        let variables = std::mem::take(&mut self.variables);
+        Answers::new(variables, self.collect())
     }
+}
+
+impl Iterator for AnswerIter<'_> {
+    type Item = Vec<Value>;
 
-    /// Gets a mutable reference to the underlying database
-    pub fn get_db_mut(&mut self) -> &mut Database {
-        &mut self.db
+    fn next(&mut self) -> Option<Vec<Value>> {
+        loop {
+            let row = self.rows.next()?;
+            if !self.seen.contains(&row) {
+                self.seen.insert(row.clone());
+                return Some(row);
+            }
+        }
+    }
+}
+
+/// Result of `Database::query`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryResult {
+    /// Whether a query without named variables, such as
+    /// `?parent("Alice", "Bob").`, `?parent(_, "Bob").` or `?male(_).`, holds
+    Bool(bool),
+    /// The bindings of the named variables of an open query, such as
+    /// `?parent(X, "Bob").`
+    Rows(Answers),
+}
+
+impl QueryResult {
+    /// Whether the query has at least one answer
+    pub fn holds(&self) -> bool {
+        match self {
+            QueryResult::Bool(holds) => *holds,
+            QueryResult::Rows(answers) => !answers.is_empty(),
+        }
+    }
+}
+
+impl From<Answers> for QueryResult {
+    fn from(answers: Answers) -> Self {
+        if answers.variables.is_empty() {
+            QueryResult::Bool(!answers.is_empty())
+        } else {
+            QueryResult::Rows(answers)
+        }
     }
 }
 
 impl Database {
-    /// Creates a new, empty Database
+    /// Creates a new, empty Database, keeping its atoms in memory
     pub fn new() -> Self {
+        Database::with_storage(AtomSet::new())
+    }
+
+    /// Creates a database keeping its atoms in `storage`, and the atoms
+    /// derived by its rules in storages made by `storage.empty()`.
+    /// A storage that kept its tuples from an earlier database, such as an
+    /// on-disk store, gives them as facts, read with the values it kept.
+    /// Rules are not kept by storages and are added again.
+    ///
+    /// # Panics
+    ///
+    /// If `storage` has tuples without the values of their symbols, or a
+    /// predicate with two numbers of arguments.
+    pub fn with_storage(storage: impl Storage + 'static) -> Self {
+        let (symbols, arities) = match storage::reopen(&storage) {
+            Ok(reopened) => reopened,
+            Err(message) => panic!("{}", message),
+        };
         Database {
-            facts: HashSet::new(),
-            relations: HashSet::new(),
+            stored_values: symbols.len(),
+            symbols,
+            derived: storage.empty(),
+            delta: storage.empty(),
+            atoms: Box::new(storage),
+            arities,
+            rules: Vec::new(),
+            strata: Vec::new(),
+            stale: false,
+        }
+    }
+
+    /// Adds atoms to the database, stopping at the first arity mismatch
+    pub fn add_atoms(&mut self, atoms: impl IntoIterator<Item = Atom>) -> Result<(), String> {
+        for atom in atoms {
+            self.add_atom(atom)?;
         }
+        Ok(())
     }
 
-    /// Adds facts to the database
-    pub fn add_facts(&mut self, facts: impl IntoIterator<Item = crate::parser::Fact>) {
-        self.facts.extend(facts);
+    /// Adds a single atom to the database.
+    /// Fails if its predicate name is invalid, if it has a variable or a
+    /// parameter, or if its predicate was already used with a different
+    /// number of arguments.
+    pub fn add_atom(&mut self, atom: Atom) -> Result<(), String> {
+        self.arities = self.check_facts(std::slice::from_ref(&atom))?;
+        self.store_fact(&atom);
+        self.stale = true;
+        Ok(())
     }
 
-    /// Adds a single fact to the database
-    pub fn add_fact(&mut self, fact: crate::parser::Fact) {
-        self.facts.insert(fact);
+    // Stores a checked fact, giving its storage the values interned since it
+    // was last given any, by this fact or by the rules
+    fn store_fact(&mut self, atom: &Atom) {
+        let tuple = self.symbols.intern_atom(atom);
+        for (symbol, value) in self.symbols.iter().skip(self.stored_values) {
+            self.atoms.add_value(symbol, value);
+        }
+        self.stored_values = self.symbols.len();
+        self.atoms.insert(&atom.name, &tuple);
     }
 
-    /// Adds relations to the database
-    pub fn add_relations(&mut self, relations: impl IntoIterator<Item = crate::parser::Relation>) {
-        self.relations.extend(relations);
+    /// Adds a fact converted from a Rust value, as `add_atom`
+    pub fn insert(&mut self, fact: impl ToFact) -> Result<(), String> {
+        self.add_atom(fact.to_atom())
     }
 
-    /// Adds a single relation to the database
-    pub fn add_relation(&mut self, relation: crate::parser::Relation) {
-        self.relations.insert(relation);
+    /// Adds facts converted from Rust values, as `db.insert_all(people.iter())`.
+    /// Nothing is added if a fact is rejected as by `add_atom`, or has a
+    /// different number of arguments than another one of the same predicate.
+    pub fn insert_all<T: ToFact>(
+        &mut self,
+        facts: impl IntoIterator<Item = T>,
+    ) -> Result<(), String> {
+        let atoms: Vec<Atom> = facts.into_iter().map(|fact| fact.to_atom()).collect();
+        self.arities = self.check_facts(&atoms)?;
+        for atom in &atoms {
+            self.store_fact(atom);
+        }
+        self.stale = true;
+        Ok(())
     }
 
-    /// Gets a reference to the facts
-    pub fn facts(&self) -> &HashSet<crate::parser::Fact> {
-        &self.facts
+    /// Removes a fact converted from a Rust value, returning whether it was
+    /// there. Relations derived from it are gone after the next evaluation.
+    pub fn retract(&mut self, fact: impl ToFact) -> bool {
+        self.retract_atom(&fact.to_atom())
     }
 
-    /// Gets a reference to the relations
-    pub fn relations(&self) -> &HashSet<crate::parser::Relation> {
-        &self.relations
+    /// Removes a fact, returning whether it was there
+    pub fn retract_atom(&mut self, atom: &Atom) -> bool {
+        let removed = self
+            .symbols
+            .get_atom(atom)
+            .is_some_and(|tuple| self.atoms.remove(&atom.name, &tuple));
+        if removed {
+            self.stale = true;
+        }
+        removed
     }
 
-    // Clears the database
-    pub fn clear(&mut self) {
-        self.facts.clear();
-        self.relations.clear();
+    /// Declares that predicate `name` has `arity` arguments, so facts and rules
+    /// using it with another number are rejected from the start.
+    /// Fails if `name` is not a predicate name, or if it was already used with
+    /// a different number.
+    pub fn declare(&mut self, name: &str, arity: usize) -> Result<(), String> {
+        check_name(name)?;
+        match self.arity(name) {
+            Some(used) if used != arity => Err(format!(
+                "{} has {} arguments, but is declared with {}",
+                name, used, arity
+            )),
+            _ => {
+                self.arities.insert(name.to_string(), arity);
+                Ok(())
+            }
+        }
+    }
+
+    /// Number of arguments of a predicate, if it has been declared or used
+    pub fn arity(&self, name: &str) -> Option<usize> {
+        self.arities.get(name).copied()
     }
 
-    // Checks if a relation exists in the database
-    pub fn contains_relation(&self, relation: &crate::parser::Relation) -> bool {
-        self.relations.contains(relation)
+    /// Checks that `atom` has as many arguments as its predicate had so far
+    pub fn check_arity(&self, atom: &Atom) -> Result<(), String> {
+        match self.arity(&atom.name) {
+            Some(arity) if arity != atom.arity() => Err(arity_mismatch(atom, arity)),
+            _ => Ok(()),
+        }
     }
 
-    // Checks if a fact exists in the database
-    pub fn contains_fact(&self, fact: &crate::parser::Fact) -> bool {
-        self.facts.contains(fact)
+    /// Adds a rule to the database.
+    /// Its relations are derived on the next call to `evaluate_rules`.
+    /// Fails if the head, a comparison or a negated item uses a variable that
+    /// no atom of the body binds, if the head has `_`, if a predicate is used
+    /// with the wrong number of arguments, if the rule puts a negation inside
+    /// a recursive cycle, or if it has a parameter.
+    pub fn add_rule(&mut self, rule: Rule) -> Result<(), String> {
+        let arities = self.check_rule_atoms(&rule)?;
+        self.rules.push(rule);
+        match stratification::stratify(&self.rules) {
+            Ok(strata) => {
+                self.strata = strata;
+                self.arities = arities;
+            }
+            Err(e) => {
+                self.rules.pop();
+                return Err(e);
+            }
+        }
+        self.stale = true;
+        Ok(())
     }
 
-    pub fn query_projection_relation(&self, q: QueryProjectionRelation) -> Vec<String> {
-        // print!("Parsed items: {:#?}", q);
-        // print!("all relations: {:#?}", self.relations);
-        let mut results = HashSet::new();
-        match (q.first.as_str(), q.second.as_str()) {
-            // if first is a variable, we return all second
-            ("_", _second) => {
-                for relation in &self.relations {
-                    if relation.name == q.name {
-                        results.insert(relation.first.clone());
-                    }
+    /// Checks that `add_rule` would accept `rule`, without adding it
+    pub(crate) fn check_rule(&self, rule: &Rule) -> Result<(), String> {
+        self.check_rule_atoms(rule)?;
+        let mut rules = self.rules.clone();
+        rules.push(rule.clone());
+        stratification::stratify(&rules).map(|_| ())
+    }
+
+    // Checks a rule on its own and against the arities of the database, giving
+    // the arities once it is added. Stratification is left to the caller.
+    fn check_rule_atoms(&self, rule: &Rule) -> Result<HashMap<String, usize>, String> {
+        if let Some(param) = rule.terms().into_iter().find_map(Term::as_param) {
+            return Err(format!(
+                "parameter ${} in rule `{}` can only be used in a query",
+                param, rule.head
+            ));
+        }
+        evaluation::check_body(&rule.definition.relations)?;
+        evaluation::check_head(rule)?;
+        self.check_arities(
+            std::iter::once(&rule.head).chain(evaluation::body_atoms(&rule.definition.relations)),
+        )
+    }
+
+    // Checks that facts have a valid predicate name and only values as
+    // arguments, as a `ToFact` may build any atom, then checks their arities
+    // as `check_arities` does
+    pub(crate) fn check_facts(&self, atoms: &[Atom]) -> Result<HashMap<String, usize>, String> {
+        for atom in atoms {
+            check_name(&atom.name)?;
+            if let Some(variable) = atom.args.iter().find(|arg| matches!(arg, Term::Var(_))) {
+                return Err(format!(
+                    "variable {} in fact `{}` is never bound",
+                    variable, atom
+                ));
+            }
+            if let Some(param) = atom.args.iter().find(|arg| matches!(arg, Term::Param(_))) {
+                return Err(format!(
+                    "parameter {} in fact `{}` can only be used in a query",
+                    param, atom
+                ));
+            }
+        }
+        self.check_arities(atoms)
+    }
+
+    // Checks atoms against each other as well as against the database, giving
+    // the arities once they are all added
+    pub(crate) fn check_arities<'a>(
+        &self,
+        atoms: impl IntoIterator<Item = &'a Atom>,
+    ) -> Result<HashMap<String, usize>, String> {
+        let mut arities = self.arities.clone();
+        for atom in atoms {
+            match arities.get(&atom.name) {
+                Some(&arity) if arity != atom.arity() => return Err(arity_mismatch(atom, arity)),
+                _ => {
+                    arities.insert(atom.name.clone(), atom.arity());
                 }
             }
-            // if second is a variable, we return all first
-            (_first, "_") => {
-                for relation in &self.relations {
-                    if relation.name == q.name {
-                        results.insert(relation.second.clone());
-                    }
+        }
+        Ok(arities)
+    }
+
+    /// Gets a reference to the rules
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Derives atoms from the rules until no new atom appears.
+    /// Strata are evaluated in order, so negated predicates are complete before
+    /// they are used. Within a stratum evaluation is semi-naive: after the first
+    /// iteration, each rule is only joined against the relations derived in the
+    /// previous iteration.
+    /// Does nothing if nothing changed since the last evaluation.
+    pub fn evaluate_rules(&mut self) {
+        if !self.stale {
+            return;
+        }
+        self.derived.clear();
+        for stratum in &self.strata {
+            let rules: Vec<&Rule> = stratum.iter().map(|&i| &self.rules[i]).collect();
+            let new_atoms = evaluation::apply_rules(self, &rules);
+            intern_into(&mut self.symbols, new_atoms, &mut *self.delta);
+            while !self.delta.is_empty() {
+                for (name, tuple) in self.delta.iter() {
+                    self.derived.insert(name, tuple);
                 }
+                let new_atoms = evaluation::apply_rules_semi_naive(self, &rules);
+                intern_into(&mut self.symbols, new_atoms, &mut *self.delta);
             }
-            _ => unimplemented!("Query projection for non-variable cases not implemented"),
         }
-        let mut results_vec: Vec<String> = results.into_iter().collect();
-        results_vec.sort();
-        results_vec
-    }
-
-    pub fn query_projection_fact(&self, q: QueryProjectionFact) -> Vec<String> {
-        // print!("Parsed items: {:#?}", q);
-        // print!("all relations: {:#?}", self.relations);
-        let mut results = HashSet::new();
-        for fact in &self.facts {
-            if fact.name == q.name {
-                results.insert(fact.first.clone());
+        self.stale = false;
+    }
+
+    /// Derives atoms from the rules by re-running every rule of a stratum
+    /// against all atoms until no new atom appears (naive evaluation).
+    /// Gives the same results as `evaluate_rules`, only slower.
+    pub fn evaluate_rules_naive(&mut self) {
+        if !self.stale {
+            return;
+        }
+        self.derived.clear();
+        for stratum in &self.strata {
+            let rules: Vec<&Rule> = stratum.iter().map(|&i| &self.rules[i]).collect();
+            loop {
+                let new_atoms = evaluation::apply_rules(self, &rules);
+                if new_atoms.is_empty() {
+                    break;
+                }
+                for atom in new_atoms {
+                    let tuple = self.symbols.intern_atom(&atom);
+                    self.derived.insert(&atom.name, &tuple);
+                }
             }
         }
-        let mut results_vec: Vec<String> = results.into_iter().collect();
-        results_vec.sort();
-        results_vec
+        self.stale = false;
+    }
+
+    /// The values of the stored atoms, each kept once
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Gets the base atoms
+    pub fn atoms(&self) -> Atoms<'_> {
+        Atoms::new(&*self.atoms, &self.symbols)
+    }
+
+    /// Gets the atoms derived from the rules
+    pub fn derived_atoms(&self) -> Atoms<'_> {
+        Atoms::new(&*self.derived, &self.symbols)
+    }
+
+    /// Gets the atoms derived in the last evaluation iteration
+    pub fn delta_atoms(&self) -> Atoms<'_> {
+        Atoms::new(&*self.delta, &self.symbols)
+    }
+
+    /// Iterates over both the base and the derived atoms
+    pub fn all_atoms(&self) -> impl Iterator<Item = Atom> + '_ {
+        self.atoms().iter().chain(self.derived_atoms().iter())
+    }
+
+    /// Iterates over the base and derived tuples of predicate `name` with
+    /// `arity` arguments
+    pub fn tuples_of<'a>(
+        &'a self,
+        name: &str,
+        arity: usize,
+    ) -> impl Iterator<Item = &'a [Symbol]> + use<'a> {
+        self.atoms()
+            .tuples_of(name, arity)
+            .chain(self.derived_atoms().tuples_of(name, arity))
+    }
+
+    /// Iterates over the base and derived tuples of predicate `name` with
+    /// `arity` arguments that have `value` as argument `column`, through an
+    /// index on that column
+    pub fn tuples_where<'a>(
+        &'a self,
+        name: &str,
+        arity: usize,
+        column: usize,
+        value: &Value,
+    ) -> impl Iterator<Item = &'a [Symbol]> + use<'a> {
+        self.tuples_matching(name, arity, &[(column, value)])
+    }
+
+    /// Iterates over the base and derived tuples of predicate `name` with
+    /// `arity` arguments that have, for each `(column, value)` of `bound`,
+    /// that value as that argument
+    pub fn tuples_matching<'a>(
+        &'a self,
+        name: &str,
+        arity: usize,
+        bound: &[(usize, &Value)],
+    ) -> impl Iterator<Item = &'a [Symbol]> + use<'a> {
+        self.atoms()
+            .tuples_matching(name, arity, bound)
+            .chain(self.derived_atoms().tuples_matching(name, arity, bound))
+    }
+
+    /// Like `tuples_matching`, with the symbols of the values
+    pub fn lookup<'a>(
+        &'a self,
+        name: &str,
+        arity: usize,
+        bound: &[(usize, Symbol)],
+    ) -> impl Iterator<Item = &'a [Symbol]> + use<'a> {
+        self.atoms()
+            .lookup(name, arity, bound)
+            .chain(self.derived_atoms().lookup(name, arity, bound))
+    }
+
+    /// Iterates over the base and derived atoms of predicate `name` with `arity` arguments
+    pub fn atoms_of<'a>(&'a self, name: &'a str, arity: usize) -> impl Iterator<Item = Atom> + 'a {
+        self.tuples_of(name, arity)
+            .map(move |tuple| self.symbols.resolve_atom(name, tuple))
+    }
+
+    // Clears the database
+    pub fn clear(&mut self) {
+        self.symbols = Symbols::new();
+        self.stored_values = 0;
+        self.atoms.clear();
+        self.derived.clear();
+        self.delta.clear();
+        self.arities.clear();
+        self.rules.clear();
+        self.strata.clear();
+        self.stale = false;
+    }
+
+    // Checks if an atom exists in the database, base or derived
+    pub fn contains_atom(&self, atom: &Atom) -> bool {
+        self.atoms().contains(atom) || self.derived_atoms().contains(atom)
+    }
+
+    /// Like `contains_atom`, with the symbols of the arguments
+    pub fn contains_tuple(&self, name: &str, tuple: &[Symbol]) -> bool {
+        self.atoms().contains_tuple(name, tuple) || self.derived_atoms().contains_tuple(name, tuple)
+    }
+
+    // Values of the argument at `position` of the atoms of `name` with
+    // `arity` arguments, one row each. With `only`, just the atoms having a
+    // given value at another position.
+    fn column_rows(
+        &self,
+        name: String,
+        arity: usize,
+        position: usize,
+        only: Option<(usize, Value)>,
+    ) -> impl Iterator<Item = Vec<Value>> + '_ {
+        let tuples: Tuples<'_> = match only {
+            Some((other, value)) => Box::new(self.tuples_where(&name, arity, other, &value)),
+            None => Box::new(self.tuples_of(&name, arity)),
+        };
+        tuples.map(move |tuple| vec![self.symbols.resolve(tuple[position]).clone()])
+    }
+
+    /// Values of the named variable of `?parent(X, _).` or `?parent(_, Y).`
+    /// Fails if the predicate does not have two arguments.
+    pub fn query_projection_relation(&self, q: QueryProjectionRelation) -> Result<Answers, String> {
+        Ok(self.query_projection_relation_iter(q)?.sorted())
+    }
+
+    /// Like `query_projection_relation`, one answer at a time
+    pub fn query_projection_relation_iter(
+        &self,
+        q: QueryProjectionRelation,
+    ) -> Result<AnswerIter<'_>, String> {
+        self.check_arity(&Atom::new(
+            q.name.clone(),
+            vec![q.first.clone(), q.second.clone()],
+        ))?;
+        let (variable, position) = match (q.first, q.second) {
+            (Term::Var(variable), _) if variable != Term::ANONYMOUS => (variable, 0),
+            (_, second) => (second.to_string(), 1),
+        };
+        Ok(AnswerIter::new(
+            vec![variable],
+            self.column_rows(q.name, 2, position, None),
+        ))
+    }
+
+    /// Values of `?male(_).`. Fails if the predicate does not have one argument.
+    pub fn query_projection_fact(&self, q: QueryProjectionFact) -> Result<Vec<Value>, String> {
+        Ok(self
+            .query_projection_fact_iter(q)?
+            .sorted()
+            .rows
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    /// Like `query_projection_fact`, one value at a time, in a column named `_`
+    pub fn query_projection_fact_iter(
+        &self,
+        q: QueryProjectionFact,
+    ) -> Result<AnswerIter<'_>, String> {
+        let anonymous = Term::Var(Term::ANONYMOUS.to_string());
+        self.check_arity(&Atom::new(q.name.clone(), vec![anonymous]))?;
+        Ok(AnswerIter::new(
+            vec![Term::ANONYMOUS.to_string()],
+            self.column_rows(q.name, 1, 0, None),
+        ))
     }
 
     // And query
-    pub fn query_conjunctive(&self, q: ConjunctiveQuery) -> Vec<String> {
-        print!("Parsed items: {:#?}", q);
-        // Filter many conditions by intersecting sets with previously matched
-        // ?parent(X, Y), male(X).
-        // For now we assume X is always the one we look for,
-        // and Y is just there to illustrate.
-        // TODO: challenge this assumption if it makes sense.
-        let mut res_relations_matching = HashSet::new();
-        let mut res_facts_matching = HashSet::new();
-        let res_matches;
-
-        for item in q.data {
-            match item {
-                crate::parser::QueryProjection::QueryProjectionRelation(rel) => {
-                    let _matching = self.query_projection_relation(QueryProjectionRelation {
-                        name: rel.name,
-                        first: rel.first,
-                        second: "_".to_string(),
-                    });
-                    let current_matching: HashSet<String> =
-                        _matching.into_iter().map(|s| s.clone()).collect();
-
-                    // If matching is not empty it needs to be an intersection with relations_matching (matching all conditions so far)
-                    if res_relations_matching.is_empty() {
-                        for res in current_matching {
-                            res_relations_matching.insert(res);
-                        }
-                    } else {
-                        // Filter existing matches with new ones
-                        let intersection: HashSet<_> = res_relations_matching
-                            .intersection(&current_matching)
-                            .cloned()
-                            .collect();
-                        res_relations_matching = intersection;
-                    }
-                }
-                crate::parser::QueryProjection::QueryProjectionFact(fact) => {
-                    let _matching =
-                        self.query_projection_fact(QueryProjectionFact { name: fact.name });
-                    let current_matching: HashSet<String> =
-                        _matching.into_iter().map(|s| s.clone()).collect();
-
-                    // If matching is not empty it needs to be an intersection with relations_matching (matching all conditions so far)
-                    if res_facts_matching.is_empty() {
-                        for res in current_matching {
-                            res_facts_matching.insert(res);
-                        }
-                    } else {
-                        // Filter existing matches with new ones
-                        let intersection: HashSet<_> = res_facts_matching
-                            .intersection(&current_matching)
-                            .cloned()
-                            .collect();
-                        res_facts_matching = intersection;
-                    }
+    // ?parent(P, C), male(P).
+    // Answers have a column for P and one for C. A query without named
+    // variables has a single empty row if it holds, and no rows otherwise.
+    pub fn query_conjunctive(&self, q: ConjunctiveQuery) -> Result<Answers, String> {
+        Ok(self.query_conjunctive_iter(q)?.sorted())
+    }
+
+    /// Like `query_conjunctive`, matching the atoms only as far as the
+    /// answers taken from it need
+    pub fn query_conjunctive_iter(&self, q: ConjunctiveQuery) -> Result<AnswerIter<'_>, String> {
+        let variables: Vec<String> = q.variables().into_iter().map(String::from).collect();
+        let body: Vec<DatalogItem> = q.data.into_iter().map(to_body_item).collect();
+        evaluation::check_body(&body)?;
+        for atom in evaluation::body_atoms(&body) {
+            self.check_arity(atom)?;
+        }
+
+        let columns = variables.clone();
+        let rows = evaluation::match_body_lazily(self, body).map(move |mut bindings| {
+            columns
+                .iter()
+                .map(|variable| {
+                    let symbol = bindings.remove(variable).expect("variable is bound");
+                    self.symbols.resolve(symbol).clone()
+                })
+                .collect()
+        });
+        Ok(AnswerIter::new(variables, rows))
+    }
+
+    pub fn relations_where_first_is(&self, rel_name: &str, first: &Value) -> Vec<Atom> {
+        let mut results: Vec<Atom> = self
+            .tuples_where(rel_name, 2, 0, first)
+            .map(|tuple| self.symbols.resolve_atom(rel_name, tuple))
+            .collect();
+        // Sort alphabetically by the second argument of the relation
+        results.sort_by(|a, b| a.args[1].cmp(&b.args[1]));
+        results
+    }
+
+    // TODO: extract common between first and second
+    pub fn relations_where_second_is(&self, rel_name: &str, second: &Value) -> Vec<Atom> {
+        let mut results: Vec<Atom> = self
+            .tuples_where(rel_name, 2, 1, second)
+            .map(|tuple| self.symbols.resolve_atom(rel_name, tuple))
+            .collect();
+        // Sort alphabetically by the first argument of the relation
+        results.sort_by(|a, b| a.args[0].cmp(&b.args[0]));
+        results
+    }
+
+    /// Writes the database to `path`, to be read back by `load_snapshot`
+    /// much faster than parsing its program again. The snapshot holds the
+    /// facts, the rules, and the relations derived from them as last evaluated.
+    /// It is written to `path` with `.tmp` appended, then renamed, so an
+    /// existing snapshot is only replaced by a complete one.
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        snapshot::write_atomically(path.as_ref(), &self.to_snapshot())
+    }
+
+    /// Reads a database written by `save_snapshot`.
+    /// Fails with `io::ErrorKind::InvalidData` if the file is not a snapshot,
+    /// was written in another format version, or is damaged.
+    pub fn load_snapshot(path: impl AsRef<Path>) -> io::Result<Database> {
+        Database::from_snapshot(&fs::read(path)?)
+    }
+
+    /// Like `load_snapshot`, keeping the atoms in `storage` as `with_storage`
+    /// does.
+    ///
+    /// # Panics
+    ///
+    /// If `storage` is not empty.
+    pub fn load_snapshot_with(
+        path: impl AsRef<Path>,
+        storage: impl Storage + 'static,
+    ) -> io::Result<Database> {
+        Database::from_snapshot_with(&fs::read(path)?, storage)
+    }
+
+    /// The snapshot `save_snapshot` writes
+    pub fn to_snapshot(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        self.symbols.encode(&mut encoder);
+        let mut arities: Vec<(&String, &usize)> = self.arities.iter().collect();
+        arities.sort();
+        encoder.len(arities.len());
+        for (name, &arity) in arities {
+            encoder.str(name);
+            encoder.len(arity);
+        }
+        // Rules are kept as source, read back by the parser
+        encoder.len(self.rules.len());
+        for rule in &self.rules {
+            encoder.str(&rule.to_string());
+        }
+        storage::encode(&*self.atoms, &mut encoder);
+        storage::encode(&*self.derived, &mut encoder);
+        encoder.u8(self.stale as u8);
+        encoder.finish()
+    }
+
+    /// Reads a snapshot given as bytes, see `load_snapshot`
+    pub fn from_snapshot(bytes: &[u8]) -> io::Result<Database> {
+        Database::from_snapshot_with(bytes, AtomSet::new())
+    }
+
+    /// Reads a snapshot given as bytes into `storage`, see `load_snapshot_with`
+    ///
+    /// # Panics
+    ///
+    /// If `storage` is not empty.
+    pub fn from_snapshot_with(
+        bytes: &[u8],
+        storage: impl Storage + 'static,
+    ) -> io::Result<Database> {
+        assert!(
+            storage.is_empty(),
+            "the storage of a new database must be empty"
+        );
+        let mut decoder = Decoder::new(bytes)?;
+        let symbols = Symbols::decode(&mut decoder)?;
+        let mut arities = HashMap::new();
+        for _ in 0..decoder.len()? {
+            let name = decoder.str()?.to_string();
+            arities.insert(name, decoder.len()?);
+        }
+        let mut rules = Vec::new();
+        for _ in 0..decoder.len()? {
+            let source = decoder.str()?;
+            match parse_rule(source) {
+                Ok(("", rule)) => rules.push(rule),
+                _ => {
+                    return Err(snapshot::invalid(format!(
+                        "invalid rule `{}` in the snapshot",
+                        source
+                    )))
                 }
             }
         }
+        let strata = stratification::stratify(&rules).map_err(snapshot::invalid)?;
+        let mut derived = storage.empty();
+        let delta = storage.empty();
+        let mut atoms = storage;
+        for (symbol, value) in symbols.iter() {
+            atoms.add_value(symbol, value);
+        }
+        storage::decode(&mut decoder, &symbols, &mut atoms)?;
+        storage::decode(&mut decoder, &symbols, &mut *derived)?;
+        let stale = decoder.u8()? != 0;
+        decoder.finish()?;
+        Ok(Database {
+            stored_values: symbols.len(),
+            symbols,
+            atoms: Box::new(atoms),
+            derived,
+            delta,
+            arities,
+            rules,
+            strata,
+            stale,
+        })
+    }
+
+    /// Answers a query given as text, such as `?parent(X, "Bob").`, deriving
+    /// the relations of the rules first.
+    /// Fails if `query` is not exactly one query, or if it does not match the
+    /// database, e.g. uses a predicate with the wrong number of arguments.
+    pub fn query(&mut self, query: &str) -> Result<QueryResult, Error> {
+        let parsed = query_engine::parse_query_source(query)?;
+        let span = parsed.span;
+        self.execute(parsed.data)
+            .map_err(|e| query_engine::query_error(query, span, e))
+    }
+
+    /// Answers a parsed or built query, deriving the relations of the rules first
+    pub fn execute(
+        &mut self,
+        query: impl Into<NonQueryDatalogItem>,
+    ) -> Result<QueryResult, String> {
+        self.evaluate_rules();
+        let query = query.into();
+        // `?male(_).` lists its values when a program runs, but it has no
+        // named variable to bind
+        let anonymous = matches!(query, NonQueryDatalogItem::QueryProjectionFact(_));
+        let answers = self.answer(query)?;
+        if anonymous {
+            Ok(QueryResult::Bool(!answers.is_empty()))
+        } else {
+            Ok(QueryResult::from(answers))
+        }
+    }
 
-        let empty_relations = res_relations_matching.is_empty();
-        let empty_facts = res_facts_matching.is_empty();
-        match (empty_relations, empty_facts) {
-            (true, true) => {
-                // If both are empty, we return an empty result
-                return Vec::new();
+    /// Answers any kind of parsed query, with the rules as last evaluated.
+    /// A query without named variables, such as `?male("Bob").`, has a single
+    /// empty row if it holds; `?male(_).` has a column named `_`.
+    /// Fails if the query still has a parameter.
+    pub fn answer(&self, query: NonQueryDatalogItem) -> Result<Answers, String> {
+        self.answers(query).map(AnswerIter::sorted)
+    }
+
+    /// Like `answer`, one answer at a time
+    pub fn answers(&self, query: NonQueryDatalogItem) -> Result<AnswerIter<'_>, String> {
+        if let Some(param) = query.terms().into_iter().find_map(Term::as_param) {
+            return Err(format!("parameter ${} has no value", param));
+        }
+        match query {
+            NonQueryDatalogItem::QueryProjectionFact(query) => {
+                self.query_projection_fact_iter(query)
             }
-            (true, false) => {
-                // If relations are empty, we return facts
-                res_matches = res_facts_matching;
+            NonQueryDatalogItem::QueryProjectionRelation(query) => {
+                self.query_projection_relation_iter(query)
             }
-            (false, true) => {
-                // If facts are empty, we return relations
-                res_matches = res_relations_matching;
+            NonQueryDatalogItem::ConjunctiveQuery(query) => self.query_conjunctive_iter(query),
+            NonQueryDatalogItem::Atom(atom) => {
+                self.check_arity(&atom)?;
+                let holds = self.contains_atom(&atom);
+                Ok(AnswerIter::new(
+                    Vec::new(),
+                    holds.then(Vec::new).into_iter(),
+                ))
             }
-            (false, false) => {
-                // If both are not empty, we need to find common elements
-                res_matches = res_relations_matching
-                    .intersection(&res_facts_matching)
-                    .cloned()
-                    .collect();
+            NonQueryDatalogItem::VariableBasedRelation(
+                VariableBasedRelation::VariableBasedRelationFirstIsVar(rel),
+            ) => {
+                self.check_arity(&Atom::new(
+                    rel.name.clone(),
+                    vec![
+                        Term::Var(rel.variable.clone()),
+                        Term::Const(rel.second.clone()),
+                    ],
+                ))?;
+                Ok(AnswerIter::new(
+                    vec![rel.variable],
+                    self.column_rows(rel.name, 2, 0, Some((1, rel.second))),
+                ))
             }
+            NonQueryDatalogItem::VariableBasedRelation(
+                VariableBasedRelation::VariableBasedRelationSecondIsVar(rel),
+            ) => {
+                self.check_arity(&Atom::new(
+                    rel.name.clone(),
+                    vec![
+                        Term::Const(rel.first.clone()),
+                        Term::Var(rel.variable.clone()),
+                    ],
+                ))?;
+                Ok(AnswerIter::new(
+                    vec![rel.variable],
+                    self.column_rows(rel.name, 2, 1, Some((0, rel.first))),
+                ))
+            }
+            NonQueryDatalogItem::Rule(_) => Err("unsupported query type".to_string()),
         }
-        let mut results: Vec<String> = res_matches.into_iter().collect();
-        results.sort_by(|a, b| a.cmp(&b));
-        return results;
     }
 
-    // TODO: relations_or_rule_where_first_is
-    // we could either precalculate all the rules or calculate them on the fly
-    // probably we precalculate all rules and iterate over which ones are relevant for this query
-    pub fn relations_where_first_is(
-        &self,
-        rel_name: &str,
-        first: &str,
-    ) -> Vec<&crate::parser::Relation> {
-        let mut results = Vec::new();
-        for relation in &self.relations {
-            if relation.name == rel_name && relation.first == first {
-                results.push(relation);
+    /// Like `query`, converting every answer, in order, into a Rust type:
+    /// `let ages: Vec<(String, i64)> = db.query_as("?age(X, A).")?;`
+    /// Fails if the answers do not have as many columns as the type, or if a
+    /// value has another type.
+    pub fn query_as<T: FromRow>(&mut self, query: &str) -> Result<Vec<T>, Error> {
+        let answers = self.query_iter(query)?.sorted();
+        Ok(convert::from_rows(&answers.variables, &answers.rows)?)
+    }
+
+    /// Like `query`, one answer at a time: only the answers taken are searched for
+    pub fn query_iter(&mut self, query: &str) -> Result<AnswerIter<'_>, Error> {
+        let parsed = query_engine::parse_query_source(query)?;
+        let span = parsed.span;
+        self.evaluate_rules();
+        self.answers(parsed.data)
+            .map_err(|e| query_engine::query_error(query, span, e))
+    }
+}
+
+/// A query parsed once and run many times with different values for its
+/// parameters, such as `$child` in `?parent(X, $child).`
+#[derive(Debug, Clone)]
+pub struct PreparedQuery {
+    query: NonQueryDatalogItem,
+    parameters: Vec<String>,
+}
+
+impl PreparedQuery {
+    /// Parses a query that may have parameters
+    pub fn new(query: &str) -> Result<Self, Error> {
+        let query = query_engine::parse_query_source(query)?.data;
+        let mut parameters: Vec<String> = Vec::new();
+        for param in query.terms().into_iter().filter_map(Term::as_param) {
+            if !parameters.iter().any(|p| p == param) {
+                parameters.push(param.to_string());
             }
         }
-        // Sort alphabetically by the 'second' field of the relation
-        results.sort_by(|a, b| a.second.cmp(&b.second));
-        return results;
+        Ok(PreparedQuery { query, parameters })
     }
 
-    // TODO: extract common between first and second
-    pub fn relations_where_second_is(
+    /// Names of the parameters, without `$`, in order of first appearance
+    pub fn parameters(&self) -> &[String] {
+        &self.parameters
+    }
+
+    /// Answers the query on `db` with a value for each parameter, given as
+    /// `("child", value)`.
+    /// Fails if a parameter has no value or a value is for no parameter.
+    pub fn execute(
         &self,
-        rel_name: &str,
-        second: &str,
-    ) -> Vec<&crate::parser::Relation> {
-        let mut results = Vec::new();
-        for relation in &self.relations {
-            if relation.name == rel_name && relation.second == second {
-                results.push(relation);
-            }
+        db: &mut Database,
+        values: &[(&str, Value)],
+    ) -> Result<QueryResult, String> {
+        if let Some((name, _)) = values
+            .iter()
+            .find(|(name, _)| !self.parameters.iter().any(|p| p == name))
+        {
+            return Err(format!("the query has no parameter ${}", name));
         }
-        // Sort alphabetically by the 'first' field of the relation
-        results.sort_by(|a, b| a.first.cmp(&b.first));
-        return results;
+        let mut query = self.query.clone();
+        query
+            .bind_params(&|param| {
+                values
+                    .iter()
+                    .find(|(name, _)| *name == param)
+                    .map(|(_, value)| value.clone())
+            })
+            .map_err(|param| format!("parameter ${} has no value", param))?;
+        db.execute(query)
+    }
+}
+
+// Interns atoms derived by the rules into `storage`, replacing what it held
+fn intern_into(symbols: &mut Symbols, atoms: HashSet<Atom>, storage: &mut dyn Storage) {
+    storage.clear();
+    for atom in atoms {
+        let tuple = symbols.intern_atom(&atom);
+        storage.insert(&atom.name, &tuple);
     }
+}
 
-    // TODO: add query for any query as string
+fn arity_mismatch(atom: &Atom, arity: usize) -> String {
+    format!(
+        "{} has {} arguments, but {} is used with {}",
+        atom.name,
+        arity,
+        atom,
+        atom.arity()
+    )
+}
+
+/// Turns an item of a conjunctive query into the equivalent rule body item
+fn to_body_item(item: QueryProjection) -> DatalogItem {
+    match item {
+        QueryProjection::Atom(atom) => DatalogItem::Atom(atom),
+        QueryProjection::Comparison(comparison) => DatalogItem::Comparison(comparison),
+        QueryProjection::Negation(item) => DatalogItem::Negation(Box::new(to_body_item(*item))),
+    }
 }
 
 impl Default for Database {
@@ -0,0 +1,303 @@
+use std::fmt;
+
+use crate::parser::{Atom, Term};
+use crate::value::Value;
+
+/// Why a row of answers could not be converted into a Rust type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    pub message: String,
+}
+
+impl ConversionError {
+    pub fn new(message: impl Into<String>) -> Self {
+        ConversionError {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// A Rust type a single value of an answer converts to
+pub trait FromValue: Sized {
+    /// Such as "a string", for error messages
+    const EXPECTED: &'static str;
+
+    /// The converted value, `None` if `value` is of another type
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for Value {
+    const EXPECTED: &'static str = "a value";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        value.as_str().map(String::from)
+    }
+}
+
+impl FromValue for i64 {
+    const EXPECTED: &'static str = "an integer";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+// Integers are numbers too, as in comparisons
+impl FromValue for f64 {
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            Value::String(_) => None,
+        }
+    }
+}
+
+/// A row of answers, with the names of its columns
+#[derive(Debug, Clone, Copy)]
+pub struct Row<'a> {
+    variables: &'a [String],
+    values: &'a [Value],
+}
+
+impl<'a> Row<'a> {
+    pub fn new(variables: &'a [String], values: &'a [Value]) -> Self {
+        Row { variables, values }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The value of the column at `index`, converted
+    pub fn get<T: FromValue>(&self, index: usize) -> Result<T, ConversionError> {
+        let Some(value) = self.values.get(index) else {
+            return Err(ConversionError::new(format!(
+                "no column {} in a row of {}",
+                index,
+                self.len()
+            )));
+        };
+        T::from_value(value).ok_or_else(|| {
+            ConversionError::new(format!(
+                "{} is {}, expected {} for {}",
+                value.to_source(),
+                value.type_name(),
+                T::EXPECTED,
+                self.variables.get(index).map_or("?", String::as_str),
+            ))
+        })
+    }
+
+    /// The value of the column of `variable`, converted
+    pub fn get_named<T: FromValue>(&self, variable: &str) -> Result<T, ConversionError> {
+        match self.variables.iter().position(|v| v == variable) {
+            Some(index) => self.get(index),
+            None => Err(ConversionError::new(format!(
+                "no variable {} in the answers",
+                variable
+            ))),
+        }
+    }
+}
+
+/// A Rust type a row of answers converts to, such as `(String, i64)`.
+/// Structs implement it by reading the columns they need:
+///
+/// ```
+/// use dataloglite::convert::{ConversionError, FromRow, Row};
+///
+/// struct Person {
+///     name: String,
+///     age: i64,
+/// }
+///
+/// impl FromRow for Person {
+///     fn from_row(row: &Row) -> Result<Self, ConversionError> {
+///         Ok(Person {
+///             name: row.get_named("Name")?,
+///             age: row.get_named("Age")?,
+///         })
+///     }
+/// }
+/// ```
+pub trait FromRow: Sized {
+    /// Number of columns expected, checked before any row is converted.
+    /// `None` if the type does not depend on it.
+    const COLUMNS: Option<usize> = None;
+
+    fn from_row(row: &Row) -> Result<Self, ConversionError>;
+}
+
+/// Converts every row of answers with `variables` as column names
+pub fn from_rows<T: FromRow>(
+    variables: &[String],
+    rows: &[Vec<Value>],
+) -> Result<Vec<T>, ConversionError> {
+    if let Some(columns) = T::COLUMNS {
+        if columns != variables.len() {
+            return Err(ConversionError::new(format!(
+                "the answers have {} columns ({}), but {} expected",
+                variables.len(),
+                variables.join(", "),
+                columns
+            )));
+        }
+    }
+    rows.iter()
+        .map(|values| {
+            T::from_row(&Row::new(variables, values)).map_err(|e| {
+                let row = values.iter().map(Value::to_source).collect::<Vec<_>>();
+                ConversionError::new(format!("{} in row ({})", e, row.join(", ")))
+            })
+        })
+        .collect()
+}
+
+// Any number of columns
+impl FromRow for Vec<Value> {
+    fn from_row(row: &Row) -> Result<Self, ConversionError> {
+        Ok(row.values.to_vec())
+    }
+}
+
+// A query without named variables converts to `()` once per answer
+impl FromRow for () {
+    const COLUMNS: Option<usize> = Some(0);
+
+    fn from_row(_row: &Row) -> Result<Self, ConversionError> {
+        Ok(())
+    }
+}
+
+macro_rules! tuple_from_row {
+    ($count:expr; $($index:tt $name:ident),+) => {
+        impl<$($name: FromValue),+> FromRow for ($($name,)+) {
+            const COLUMNS: Option<usize> = Some($count);
+
+            fn from_row(row: &Row) -> Result<Self, ConversionError> {
+                Ok(($(row.get::<$name>($index)?,)+))
+            }
+        }
+    };
+}
+
+tuple_from_row!(1; 0 A);
+tuple_from_row!(2; 0 A, 1 B);
+tuple_from_row!(3; 0 A, 1 B, 2 C);
+tuple_from_row!(4; 0 A, 1 B, 2 C, 3 D);
+tuple_from_row!(5; 0 A, 1 B, 2 C, 3 D, 4 E);
+tuple_from_row!(6; 0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
+
+/// A Rust value stored as a fact, such as a `Person` stored as
+/// `person("Alice", 52)`. Structs implement it with `to_fact!`:
+///
+/// ```
+/// use dataloglite::api::Database;
+/// use dataloglite::to_fact;
+///
+/// struct Person {
+///     name: String,
+///     age: i64,
+/// }
+///
+/// to_fact!(Person => person(name, age));
+///
+/// let people = [Person { name: "Alice".to_string(), age: 52 }];
+/// let mut db = Database::new();
+/// db.insert_all(people.iter()).unwrap();
+/// ```
+///
+/// Tuples are facts too, with the predicate first, as `("parent", "Alice", "Bob")`.
+pub trait ToFact {
+    fn predicate(&self) -> &str;
+
+    fn arguments(&self) -> Vec<Value>;
+
+    fn to_atom(&self) -> Atom {
+        Atom::new(
+            self.predicate(),
+            self.arguments().into_iter().map(Term::Const).collect(),
+        )
+    }
+}
+
+impl<T: ToFact + ?Sized> ToFact for &T {
+    fn predicate(&self) -> &str {
+        (**self).predicate()
+    }
+
+    fn arguments(&self) -> Vec<Value> {
+        (**self).arguments()
+    }
+}
+
+/// Implements `ToFact` for a struct, listing the fields that are the
+/// arguments of the predicate in order. Fields convert with `Value::from`.
+///
+/// ```
+/// # use dataloglite::to_fact;
+/// struct Parent(String, String);
+///
+/// to_fact!(Parent => parent(0, 1));
+/// ```
+#[macro_export]
+macro_rules! to_fact {
+    ($type:ty => $predicate:ident($($field:tt),* $(,)?)) => {
+        impl $crate::convert::ToFact for $type {
+            fn predicate(&self) -> &str {
+                stringify!($predicate)
+            }
+
+            fn arguments(&self) -> Vec<$crate::value::Value> {
+                vec![$($crate::value::Value::from(self.$field.clone())),*]
+            }
+        }
+    };
+}
+
+macro_rules! tuple_to_fact {
+    ($($index:tt $name:ident),+) => {
+        impl<P: AsRef<str>, $($name: Clone + Into<Value>),+> ToFact for (P, $($name,)+) {
+            fn predicate(&self) -> &str {
+                self.0.as_ref()
+            }
+
+            fn arguments(&self) -> Vec<Value> {
+                vec![$(self.$index.clone().into()),+]
+            }
+        }
+    };
+}
+
+tuple_to_fact!(1 A);
+tuple_to_fact!(1 A, 2 B);
+tuple_to_fact!(1 A, 2 B, 3 C);
+tuple_to_fact!(1 A, 2 B, 3 C, 4 D);
+tuple_to_fact!(1 A, 2 B, 3 C, 4 D, 5 E);
+tuple_to_fact!(1 A, 2 B, 3 C, 4 D, 5 E, 6 F);
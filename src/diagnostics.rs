@@ -0,0 +1,291 @@
+use std::fmt;
+use std::ops::Range;
+
+use nom::error::{ContextError, ErrorKind, ParseError};
+use nom::Input;
+
+use crate::parser::{parse_datalog_item, parse_whitespace, DatalogItem};
+
+/// Error produced by the parsers of `parser.rs`: where parsing stopped and
+/// what would have been accepted there
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError<I> {
+    pub input: I,
+    /// Such as "`)`" or "a variable", empty if nothing more precise is known
+    pub expected: Vec<String>,
+}
+
+pub type IResult<I, O> = nom::IResult<I, O, SyntaxError<I>>;
+
+impl<I: Input> ParseError<I> for SyntaxError<I> {
+    fn from_error_kind(input: I, _kind: ErrorKind) -> Self {
+        SyntaxError {
+            input,
+            expected: Vec::new(),
+        }
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+
+    fn from_char(input: I, c: char) -> Self {
+        SyntaxError {
+            input,
+            expected: vec![format!("`{}`", c)],
+        }
+    }
+
+    // Keep the branch of an `alt` that got furthest, merging those that got equally far
+    fn or(mut self, other: Self) -> Self {
+        match self.input.input_len().cmp(&other.input.input_len()) {
+            std::cmp::Ordering::Less => self,
+            std::cmp::Ordering::Greater => other,
+            std::cmp::Ordering::Equal => {
+                for expected in other.expected {
+                    if !self.expected.contains(&expected) {
+                        self.expected.push(expected);
+                    }
+                }
+                self
+            }
+        }
+    }
+}
+
+impl<I: Input> ContextError<I> for SyntaxError<I> {
+    // A labelled parser that failed before consuming anything is reported
+    // by its label, such as "a variable" instead of "an uppercase letter".
+    // Labels such as "`,` or `)`" list several alternatives.
+    fn add_context(input: I, ctx: &'static str, other: Self) -> Self {
+        if other.input.input_len() == input.input_len() {
+            SyntaxError {
+                input,
+                expected: ctx.split(" or ").map(String::from).collect(),
+            }
+        } else {
+            other
+        }
+    }
+}
+
+/// Where a node of the syntax tree was read from.
+///
+/// Spans are located in their source by `parse_datalog`, or by `locate` for
+/// nodes read with the other parsers. Until then they know no position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    // Byte offsets of the first character and just past the last one. Before
+    // the span is located they are counted from the end of the source.
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Span {
+    // While parsing only the remaining input is known, so offsets are first
+    // counted from the end of the source and turned around by `locate`
+    pub(crate) fn between(before: &str, after: &str) -> Self {
+        Span {
+            start: before.len(),
+            end: after.len(),
+            line: 0,
+            column: 0,
+        }
+    }
+
+    /// Whether `locate` has computed the position in the source
+    pub fn is_located(&self) -> bool {
+        self.line != 0
+    }
+
+    /// Resolves the span against the source it was parsed from.
+    /// Does nothing if it already was.
+    pub fn locate(&mut self, source: &str) {
+        if self.is_located() || self.start > source.len() {
+            return;
+        }
+        self.start = source.len() - self.start;
+        self.end = source.len() - self.end;
+        (self.line, self.column) = line_and_column(source, self.start);
+    }
+
+    /// 1-based line of the first character, 0 until the span is located
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// 1-based column of the first character, counted in characters, 0 until
+    /// the span is located
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Byte range of the span in its source, once located
+    pub fn range(&self) -> Option<Range<usize>> {
+        self.is_located().then_some(self.start..self.end)
+    }
+}
+
+// 1-based line and column, counted in characters, of a byte offset
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    (
+        source[..offset].matches('\n').count() + 1,
+        source[line_start..offset].chars().count() + 1,
+    )
+}
+
+/// An error located in a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub file: String,
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, counted in characters
+    pub column: usize,
+    pub message: String,
+    /// The whole source line the error is on
+    pub snippet: String,
+}
+
+impl Diagnostic {
+    fn new(file: &str, source: &str, offset: usize, message: String) -> Self {
+        let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[offset..]
+            .find('\n')
+            .map_or(source.len(), |i| offset + i);
+        let (line, column) = line_and_column(source, offset);
+        Diagnostic {
+            file: file.to_string(),
+            line,
+            column,
+            message,
+            snippet: source[line_start..line_end]
+                .trim_end_matches('\r')
+                .to_string(),
+        }
+    }
+
+    /// An error about the statement at `span`, such as an unsafe rule.
+    /// `span` is located in `source` if it was not already.
+    pub fn at(file: &str, source: &str, mut span: Span, message: impl Into<String>) -> Self {
+        span.locate(source);
+        Diagnostic::new(file, source, span.start, message.into())
+    }
+}
+
+// error: expected `,` or `)`, found `"`
+//  --> family.datalog:3:16
+//   |
+// 3 | parent("Alice" "Bob").
+//   |                ^
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "error: {}", self.message)?;
+        writeln!(
+            f,
+            "{}--> {}:{}:{}",
+            gutter, self.file, self.line, self.column
+        )?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.snippet)?;
+        write!(f, "{} | {}^", gutter, " ".repeat(self.column - 1))
+    }
+}
+
+// "`,` or `)`", "a variable, `_` or a value"
+fn join_expected(expected: &[String]) -> String {
+    match expected {
+        [] => "a statement".to_string(),
+        [only] => only.clone(),
+        [init @ .., last] => format!("{} or {}", init.join(", "), last),
+    }
+}
+
+fn describe_found(rest: &str) -> String {
+    match rest.chars().next() {
+        None => "end of input".to_string(),
+        Some('\n') | Some('\r') => "end of line".to_string(),
+        Some(c) => format!("`{}`", c),
+    }
+}
+
+/// Offset just past the `.` ending the statement that failed at `error`,
+/// ignoring dots in strings, comments and numbers.
+/// Scanning starts at the statement so quotes opened before the error are seen.
+fn recovery_point(source: &str, start: usize, error: usize) -> usize {
+    let bytes = source.as_bytes();
+    let mut i = start;
+    let mut in_string = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_string => i += 1,
+            b'"' => in_string = !in_string,
+            b'\n' if in_string => in_string = false,
+            b'/' if !in_string && bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                continue;
+            }
+            b'/' if !in_string && bytes.get(i + 1) == Some(&b'*') => {
+                i = source[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |n| i + 2 + n + 2);
+                continue;
+            }
+            b'.' if !in_string && i >= error => {
+                let in_number = i > 0
+                    && bytes[i - 1].is_ascii_digit()
+                    && bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+                if !in_number {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+/// Parses a whole program, reporting every malformed statement instead of
+/// stopping at the first one. After an error parsing resumes after the next `.`.
+pub fn parse_with_diagnostics(source: &str, file: &str) -> (Vec<DatalogItem>, Vec<Diagnostic>) {
+    let mut items = Vec::new();
+    let mut diagnostics = Vec::new();
+    let offset = |rest: &str| source.len() - rest.len();
+
+    let mut rest = source;
+    loop {
+        rest = match parse_whitespace(rest) {
+            Ok((rest, _)) => rest,
+            Err(_) => rest,
+        };
+        if rest.is_empty() {
+            break;
+        }
+        match parse_datalog_item(rest) {
+            Ok((remaining, mut item)) => {
+                item.locate(source);
+                items.push(item);
+                rest = remaining;
+            }
+            Err(nom::Err::Error(error)) | Err(nom::Err::Failure(error)) => {
+                // Point at the next token rather than at the spaces before it
+                let found = error.input.trim_start_matches([' ', '\t']);
+                let message = format!(
+                    "expected {}, found {}",
+                    join_expected(&error.expected),
+                    describe_found(found)
+                );
+                let error_offset = offset(found);
+                diagnostics.push(Diagnostic::new(file, source, error_offset, message));
+                rest = &source[recovery_point(source, offset(rest), error_offset)..];
+            }
+            Err(nom::Err::Incomplete(_)) => unreachable!("parsers are complete"),
+        }
+    }
+    (items, diagnostics)
+}
@@ -0,0 +1,394 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::api::Database;
+use crate::parser::{Atom, DatalogItem, Rule, Term};
+use crate::storage::{Symbol, Tuples};
+use crate::value::Value;
+
+/// Symbols of the values bound to the variables of a rule while its body is
+/// being matched. They are resolved to values only in answers.
+pub type Bindings = HashMap<String, Symbol>;
+
+/// Binds the variable `pattern` to `symbol`, or checks that an existing binding
+/// agrees with it. Constants must have `symbol` as value, `_` matches anything.
+fn unify(db: &Database, bindings: &mut Bindings, pattern: &Term, symbol: Symbol) -> bool {
+    let variable = match pattern {
+        Term::Const(constant) => return db.symbols().resolve(symbol) == constant,
+        // Parameters are replaced by values before a query runs
+        Term::Param(_) => return false,
+        Term::Var(_) => match pattern.as_var() {
+            Some(variable) => variable,
+            None => return true,
+        },
+    };
+    match bindings.get(variable) {
+        Some(&bound) => bound == symbol,
+        None => {
+            bindings.insert(variable.to_string(), symbol);
+            true
+        }
+    }
+}
+
+/// Which atoms a body item is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// Base and derived atoms
+    All,
+    /// Only the atoms derived in the previous iteration
+    Delta,
+}
+
+/// Unifies the arguments of `pattern` with the symbols of a stored tuple
+fn unify_tuple(db: &Database, bindings: &mut Bindings, pattern: &Atom, tuple: &[Symbol]) -> bool {
+    pattern
+        .args
+        .iter()
+        .zip(tuple)
+        .all(|(arg, &symbol)| unify(db, bindings, arg, symbol))
+}
+
+/// The tuples of `source` that may match `pattern`. The arguments that are
+/// constants or bound variables are given to the storage, which looks up the
+/// tuples with their symbols instead of scanning the predicate.
+fn candidates<'a>(
+    db: &'a Database,
+    pattern: &Atom,
+    bindings: &Bindings,
+    source: Source,
+) -> Tuples<'a> {
+    let (name, arity) = (pattern.name.as_str(), pattern.arity());
+    let mut bound = Vec::new();
+    for (column, arg) in pattern.args.iter().enumerate() {
+        let symbol = match arg {
+            Term::Const(value) => match db.symbols().get(value) {
+                Some(symbol) => symbol,
+                // A constant that nothing stored has
+                None => return Box::new(std::iter::empty()),
+            },
+            Term::Var(variable) => match bindings.get(variable) {
+                Some(&symbol) => symbol,
+                None => continue,
+            },
+            Term::Param(_) => continue,
+        };
+        bound.push((column, symbol));
+    }
+    match source {
+        Source::All => Box::new(db.lookup(name, arity, &bound)),
+        Source::Delta => db.delta_atoms().lookup(name, arity, &bound),
+    }
+}
+
+/// Looks up the value of a comparison operand, `None` if its variable is unbound
+fn resolve<'a>(db: &'a Database, operand: &'a Term, bindings: &Bindings) -> Option<&'a Value> {
+    match operand {
+        Term::Var(name) => Some(db.symbols().resolve(*bindings.get(name)?)),
+        Term::Const(value) => Some(value),
+        Term::Param(_) => None,
+    }
+}
+
+/// Names of the variables of an atom
+fn item_variables(item: &DatalogItem) -> Vec<&str> {
+    match item {
+        DatalogItem::Atom(atom) => atom.variables().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Atoms of a rule body, negated or not
+pub fn body_atoms(body: &[DatalogItem]) -> impl Iterator<Item = &Atom> {
+    body.iter().filter_map(|item| match item {
+        DatalogItem::Atom(atom) => Some(atom),
+        DatalogItem::Negation(inner) => match inner.as_ref() {
+            DatalogItem::Atom(atom) => Some(atom),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// Evaluates a comparison or a negated item against a set of bindings,
+/// `None` if one of its variables is not bound yet
+fn filter(db: &Database, item: &DatalogItem, bindings: &Bindings) -> Option<bool> {
+    match item {
+        DatalogItem::Comparison(comparison) => {
+            let left = resolve(db, &comparison.left, bindings)?;
+            let right = resolve(db, &comparison.right, bindings)?;
+            Some(comparison.operator.compare(left, right))
+        }
+        DatalogItem::Negation(inner) => {
+            if item_variables(inner)
+                .iter()
+                .any(|variable| !bindings.contains_key(*variable))
+            {
+                return None;
+            }
+            let DatalogItem::Atom(pattern) = inner.as_ref() else {
+                return Some(true);
+            };
+            let mut tuples = candidates(db, pattern, bindings, Source::All);
+            Some(!tuples.any(|tuple| unify_tuple(db, &mut bindings.clone(), pattern, tuple)))
+        }
+        _ => Some(true),
+    }
+}
+
+/// Checks that every variable used in a comparison or a negated item is bound
+/// by an atom of the same body, otherwise it can never be evaluated
+pub fn check_body(body: &[DatalogItem]) -> Result<(), String> {
+    let bound: HashSet<&str> = body.iter().flat_map(item_variables).collect();
+    for item in body {
+        let unbound = match item {
+            DatalogItem::Comparison(comparison) => comparison
+                .variables()
+                .find(|v| !bound.contains(v))
+                .map(|v| (v, format!("comparison `{}`", comparison))),
+            DatalogItem::Negation(inner) => match inner.as_ref() {
+                DatalogItem::Atom(atom) => atom
+                    .variables()
+                    .find(|v| !bound.contains(v))
+                    .map(|v| (v, format!("negated `!{}`", atom))),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some((variable, context)) = unbound {
+            return Err(format!(
+                "variable {} in {} is never bound",
+                variable, context
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every variable of the head of `rule` is bound by an atom of its
+/// body, so that every atom it derives is ground
+pub fn check_head(rule: &Rule) -> Result<(), String> {
+    if rule
+        .head
+        .args
+        .iter()
+        .any(|arg| matches!(arg, Term::Var(v) if v == Term::ANONYMOUS))
+    {
+        return Err(format!("`_` in head `{}` has no value", rule.head));
+    }
+    let bound: HashSet<&str> = rule
+        .definition
+        .relations
+        .iter()
+        .flat_map(item_variables)
+        .collect();
+    match rule.head.variables().find(|v| !bound.contains(v)) {
+        Some(variable) => Err(format!(
+            "variable {} in head `{}` is never bound",
+            variable, rule.head
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Bindings that satisfy a body, found one at a time by matching its atoms
+/// depth first. Each comparison and negated item is checked as soon as its
+/// variables are bound.
+pub struct BodyMatches<'a> {
+    db: &'a Database,
+    atoms: Vec<Atom>,
+    // The atom matched against the delta of the previous iteration, if any
+    delta: Option<usize>,
+    // `filters[k]` are checked once the first `k` atoms are matched
+    filters: Vec<Vec<DatalogItem>>,
+    // Bindings of the first `k` atoms and the candidates for atom `k`
+    stack: Vec<(Bindings, Tuples<'a>)>,
+    // Set for a body without atoms whose filters hold, yielding one empty match
+    empty_match: bool,
+}
+
+/// Matches `body` lazily; `check_body` must have accepted it
+pub fn match_body_lazily(db: &Database, body: Vec<DatalogItem>) -> BodyMatches<'_> {
+    match_body_with_delta(db, body, None)
+}
+
+/// Like `match_body_lazily`, but the `delta`th atom of the body (if any) only
+/// matches atoms derived in the previous iteration
+fn match_body_with_delta(
+    db: &Database,
+    body: Vec<DatalogItem>,
+    delta: Option<usize>,
+) -> BodyMatches<'_> {
+    let mut atoms = Vec::new();
+    let mut filter_items = Vec::new();
+    for item in body {
+        match item {
+            DatalogItem::Atom(atom) => atoms.push(atom),
+            item => filter_items.push(item),
+        }
+    }
+
+    let mut filters: Vec<Vec<DatalogItem>> = vec![Vec::new(); atoms.len() + 1];
+    for item in filter_items {
+        let variables: Vec<&str> = match &item {
+            DatalogItem::Comparison(comparison) => comparison.variables().collect(),
+            DatalogItem::Negation(inner) => item_variables(inner),
+            _ => Vec::new(),
+        };
+        let mut bound: HashSet<&str> = HashSet::new();
+        let mut position = 0;
+        while position < atoms.len() && !variables.iter().all(|v| bound.contains(v)) {
+            bound.extend(atoms[position].variables());
+            position += 1;
+        }
+        filters[position].push(item);
+    }
+
+    let mut matches = BodyMatches {
+        db,
+        atoms,
+        delta,
+        filters,
+        stack: Vec::new(),
+        empty_match: false,
+    };
+    let bindings = Bindings::new();
+    if matches.passes(0, &bindings) {
+        if matches.atoms.is_empty() {
+            matches.empty_match = true;
+        } else {
+            matches.push(bindings);
+        }
+    }
+    matches
+}
+
+impl<'a> BodyMatches<'a> {
+    fn passes(&self, matched: usize, bindings: &Bindings) -> bool {
+        self.filters[matched]
+            .iter()
+            .all(|item| filter(self.db, item, bindings) == Some(true))
+    }
+
+    // Starts on the candidates for the next atom
+    fn push(&mut self, bindings: Bindings) {
+        let position = self.stack.len();
+        let source = if self.delta == Some(position) {
+            Source::Delta
+        } else {
+            Source::All
+        };
+        let candidates = candidates(self.db, &self.atoms[position], &bindings, source);
+        self.stack.push((bindings, candidates));
+    }
+}
+
+impl Iterator for BodyMatches<'_> {
+    type Item = Bindings;
+
+    fn next(&mut self) -> Option<Bindings> {
+        if self.empty_match {
+            self.empty_match = false;
+            return Some(Bindings::new());
+        }
+        loop {
+            let matched = self.stack.len();
+            let (bindings, candidates) = self.stack.last_mut()?;
+            let Some(tuple) = candidates.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let mut candidate = bindings.clone();
+            let pattern = &self.atoms[matched - 1];
+            if !unify_tuple(self.db, &mut candidate, pattern, tuple)
+                || !self.passes(matched, &candidate)
+            {
+                continue;
+            }
+            if matched == self.atoms.len() {
+                return Some(candidate);
+            }
+            self.push(candidate);
+        }
+    }
+}
+
+/// The symbols of the head of `rule` for a set of body bindings, `None` if
+/// one of its constants is not stored yet
+fn head_tuple(db: &Database, rule: &Rule, bindings: &Bindings) -> Option<Vec<Symbol>> {
+    rule.head
+        .args
+        .iter()
+        .map(|arg| match arg {
+            Term::Var(variable) => Some(bindings[variable.as_str()]),
+            Term::Const(value) => db.symbols().get(value),
+            Term::Param(_) => None,
+        })
+        .collect()
+}
+
+/// Builds the head of `rule` from a set of body bindings
+fn derive_head(db: &Database, rule: &Rule, bindings: &Bindings) -> Atom {
+    let args = rule
+        .head
+        .args
+        .iter()
+        .map(|arg| match arg {
+            Term::Var(variable) => {
+                Term::Const(db.symbols().resolve(bindings[variable.as_str()]).clone())
+            }
+            constant => constant.clone(),
+        })
+        .collect();
+    Atom::new(rule.head.name.clone(), args)
+}
+
+/// Collects the heads derived from `matches` that are not already in the
+/// database. Heads are only built from values when they are new.
+fn collect_new(
+    db: &Database,
+    rule: &Rule,
+    matches: impl Iterator<Item = Bindings>,
+    derived: &mut HashSet<Atom>,
+) {
+    for bindings in matches {
+        if let Some(tuple) = head_tuple(db, rule, &bindings) {
+            if db.contains_tuple(&rule.head.name, &tuple) {
+                continue;
+            }
+        }
+        derived.insert(derive_head(db, rule, &bindings));
+    }
+}
+
+/// Applies every rule once and returns the atoms not already in the database
+pub fn apply_rules(db: &Database, rules: &[&Rule]) -> HashSet<Atom> {
+    let mut derived = HashSet::new();
+    for rule in rules {
+        let matches = match_body_lazily(db, rule.definition.relations.clone());
+        collect_new(db, rule, matches, &mut derived);
+    }
+    derived
+}
+
+/// Applies every rule once, joining each body atom whose predicate is derived
+/// by `rules` against the delta of the previous iteration only.
+/// Returns the atoms not already in the database.
+pub fn apply_rules_semi_naive(db: &Database, rules: &[&Rule]) -> HashSet<Atom> {
+    let derived_names: HashSet<&str> = rules.iter().map(|r| r.head.name.as_str()).collect();
+    let mut derived = HashSet::new();
+    for rule in rules {
+        let body = &rule.definition.relations;
+        let atoms = body.iter().filter_map(|item| match item {
+            DatalogItem::Atom(atom) => Some(atom),
+            _ => None,
+        });
+        for (position, atom) in atoms.enumerate() {
+            if !derived_names.contains(atom.name.as_str()) {
+                continue;
+            }
+            let matches = match_body_with_delta(db, body.clone(), Some(position));
+            collect_new(db, rule, matches, &mut derived);
+        }
+    }
+    derived
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::api::{Database, QueryResult};
+use crate::convert::{FromRow, ToFact};
+use crate::parser::{parse_rule, Atom, Rule, Term};
+use crate::query_engine;
+use crate::snapshot::{self, crc32, Decoder, Encoder};
+use crate::storage::{AtomSet, Storage};
+
+// A journal is:
+//   "DLLJRNL\0"  magic bytes
+//   u32          format version
+// then one record per change, in the order they were made:
+//   u32          length of the payload
+//   u32          CRC-32 of the length
+//   u32          CRC-32 of the payload
+//   ...          payload: a kind, then a fact or the source of a rule
+// Numbers and strings are written as in snapshots.
+const MAGIC: &[u8; 8] = b"DLLJRNL\0";
+
+/// Version of the journal format written by this build
+pub const VERSION: u32 = 2;
+
+const HEADER: usize = MAGIC.len() + 4;
+const RECORD_HEADER: usize = 12;
+
+const INSERT: u8 = 0;
+const RETRACT: u8 = 1;
+const RULE: u8 = 2;
+
+/// Why a change to a journaled database was not made
+#[derive(Debug)]
+pub enum Error {
+    /// The database rejected the change, nothing was written
+    Rejected(String),
+    /// Writing the journal failed, the change was not applied
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Rejected(message) => write!(f, "{}", message),
+            Error::Io(e) => write!(f, "failed to write the journal: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Rejected(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A database whose changes survive the process.
+/// Every insertion, retraction and rule is appended to a journal file, and
+/// synced, before it is applied. Opening the database loads its snapshot and
+/// replays the journal on top of it, and `compact` folds the journal into a
+/// new snapshot.
+///
+/// A crash while appending leaves a torn record at the end of the journal:
+/// it is dropped when the journal is opened, as the change it held was never
+/// applied.
+pub struct JournaledDatabase {
+    db: Database,
+    snapshot: PathBuf,
+    journal: File,
+    // Length of the journal up to its last complete record
+    len: u64,
+}
+
+impl JournaledDatabase {
+    /// Opens the database saved in `snapshot` and `journal`, creating
+    /// whichever does not exist yet.
+    /// Fails with `io::ErrorKind::InvalidData` if one of them is damaged
+    /// other than by a torn last record.
+    pub fn open(snapshot: impl AsRef<Path>, journal: impl AsRef<Path>) -> io::Result<Self> {
+        JournaledDatabase::open_with(snapshot, journal, AtomSet::new())
+    }
+
+    /// Like `open`, keeping the atoms in `storage` as
+    /// `Database::with_storage` does. The journal and the snapshot stay the
+    /// only files the database is saved in.
+    ///
+    /// # Panics
+    ///
+    /// If `storage` is not empty.
+    pub fn open_with(
+        snapshot: impl AsRef<Path>,
+        journal: impl AsRef<Path>,
+        storage: impl Storage + 'static,
+    ) -> io::Result<Self> {
+        let snapshot = snapshot.as_ref().to_path_buf();
+        let mut db = match fs::read(&snapshot) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Database::with_storage(storage),
+            read => Database::from_snapshot_with(&read?, storage)?,
+        };
+        let journal = journal.as_ref();
+        let bytes = match fs::read(journal) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            read => read?,
+        };
+        // A new journal is written with its header in one go, so one shorter
+        // than its header is damaged rather than torn
+        let len = if bytes.is_empty() {
+            let mut header = MAGIC.to_vec();
+            header.extend_from_slice(&VERSION.to_le_bytes());
+            snapshot::write_atomically(journal, &header)?;
+            HEADER
+        } else {
+            check_header(&bytes)?;
+            replay(&mut db, &bytes)?
+        };
+        let file = OpenOptions::new().read(true).write(true).open(journal)?;
+        // Drops a torn last record, so that new records follow complete ones
+        file.set_len(len as u64)?;
+        file.sync_data()?;
+        Ok(JournaledDatabase {
+            db,
+            snapshot,
+            journal: file,
+            len: len as u64,
+        })
+    }
+
+    /// Gets a reference to the underlying database.
+    /// Changes go through the journaled database, so there is no mutable one.
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Closes the journal, keeping the database
+    pub fn into_database(self) -> Database {
+        self.db
+    }
+
+    /// Adds a fact, as `Database::insert`
+    pub fn insert(&mut self, fact: impl ToFact) -> Result<(), Error> {
+        self.insert_all([fact])
+    }
+
+    /// Adds facts, as `Database::insert_all`. They are written to the journal
+    /// together, and synced once.
+    pub fn insert_all<T: ToFact>(
+        &mut self,
+        facts: impl IntoIterator<Item = T>,
+    ) -> Result<(), Error> {
+        let atoms: Vec<Atom> = facts.into_iter().map(|fact| fact.to_atom()).collect();
+        self.db.check_facts(&atoms).map_err(Error::Rejected)?;
+        let mut records = Vec::new();
+        for atom in &atoms {
+            records.extend(fact_record(INSERT, atom));
+        }
+        self.append(&records)?;
+        self.db
+            .add_atoms(atoms)
+            .expect("facts are checked before they are journaled");
+        Ok(())
+    }
+
+    /// Removes a fact, as `Database::retract`. Nothing is written if the
+    /// fact is not in the database.
+    pub fn retract(&mut self, fact: impl ToFact) -> Result<bool, Error> {
+        let atom = fact.to_atom();
+        if !self.db.atoms().contains(&atom) {
+            return Ok(false);
+        }
+        self.append(&fact_record(RETRACT, &atom))?;
+        Ok(self.db.retract_atom(&atom))
+    }
+
+    /// Adds a rule, as `Database::add_rule`
+    pub fn add_rule(&mut self, rule: Rule) -> Result<(), Error> {
+        self.db.check_rule(&rule).map_err(Error::Rejected)?;
+        self.append(&rule_record(&rule))?;
+        self.db
+            .add_rule(rule)
+            .expect("rules are checked before they are journaled");
+        Ok(())
+    }
+
+    /// Answers a query given as text, as `Database::query`
+    pub fn query(&mut self, query: &str) -> Result<QueryResult, query_engine::Error> {
+        self.db.query(query)
+    }
+
+    /// Answers a query given as text, converting the answers, as
+    /// `Database::query_as`
+    pub fn query_as<T: FromRow>(&mut self, query: &str) -> Result<Vec<T>, query_engine::Error> {
+        self.db.query_as(query)
+    }
+
+    /// Saves the database, with its derived relations, as the new snapshot
+    /// and empties the journal.
+    /// The snapshot is replaced at once, so a crash leaves either the old
+    /// snapshot and the full journal or the new snapshot. If the journal was
+    /// not emptied yet, replaying it on the new snapshot changes nothing.
+    pub fn compact(&mut self) -> io::Result<()> {
+        self.db.evaluate_rules();
+        snapshot::write_atomically(&self.snapshot, &self.db.to_snapshot())?;
+        self.journal.set_len(HEADER as u64)?;
+        self.journal.sync_data()?;
+        self.len = HEADER as u64;
+        Ok(())
+    }
+
+    // Appends records after the last complete one and syncs them. On failure
+    // the journal is cut back, so no torn record is left before later ones.
+    fn append(&mut self, records: &[u8]) -> io::Result<()> {
+        let written = write_at_end(&mut self.journal, self.len, records);
+        if let Err(e) = written {
+            let _ = self.journal.set_len(self.len);
+            return Err(e);
+        }
+        self.len += records.len() as u64;
+        Ok(())
+    }
+}
+
+fn write_at_end(file: &mut File, len: u64, bytes: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(len))?;
+    file.write_all(bytes)?;
+    file.sync_data()
+}
+
+fn check_header(bytes: &[u8]) -> io::Result<()> {
+    if bytes.len() < HEADER || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(snapshot::invalid("not a dataloglite journal"));
+    }
+    let version = u32::from_le_bytes(bytes[MAGIC.len()..HEADER].try_into().unwrap());
+    if version != VERSION {
+        return Err(snapshot::invalid(format!(
+            "journal format version {} is not supported, expected {}",
+            version, VERSION
+        )));
+    }
+    Ok(())
+}
+
+fn record(payload: Encoder) -> Vec<u8> {
+    let payload = payload.into_bytes();
+    let mut record = Encoder::raw();
+    let len = u32::try_from(payload.len()).expect("journal record too long");
+    record.u32(len);
+    record.u32(crc32(&len.to_le_bytes()));
+    record.u32(crc32(&payload));
+    let mut record = record.into_bytes();
+    record.extend(payload);
+    record
+}
+
+fn fact_record(kind: u8, atom: &Atom) -> Vec<u8> {
+    let mut payload = Encoder::raw();
+    payload.u8(kind);
+    payload.str(&atom.name);
+    payload.len(atom.arity());
+    for arg in &atom.args {
+        payload.value(arg.as_value().expect("facts are ground"));
+    }
+    record(payload)
+}
+
+fn rule_record(rule: &Rule) -> Vec<u8> {
+    let mut payload = Encoder::raw();
+    payload.u8(RULE);
+    payload.str(&rule.to_string());
+    record(payload)
+}
+
+// Applies the records of a journal to `db`, giving the length of the journal
+// up to its last complete record.
+// A record is appended whole, so a crash can only leave the last one short,
+// or with a payload that was not all written. Its length has its own
+// checksum, so a damaged length is told apart from a torn record and never
+// drops the records after it.
+fn replay(db: &mut Database, bytes: &[u8]) -> io::Result<usize> {
+    let mut position = HEADER;
+    while position < bytes.len() {
+        let rest = &bytes[position..];
+        if rest.len() < RECORD_HEADER {
+            break;
+        }
+        let damaged =
+            || snapshot::invalid(format!("journal record at byte {} is damaged", position));
+        let len_bytes = &rest[..4];
+        let len_checksum = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+        if crc32(len_bytes) != len_checksum {
+            return Err(damaged());
+        }
+        let len = u32::from_le_bytes(len_bytes.try_into().unwrap()) as usize;
+        let checksum = u32::from_le_bytes(rest[8..RECORD_HEADER].try_into().unwrap());
+        let Some(payload) = rest.get(RECORD_HEADER..RECORD_HEADER + len) else {
+            break;
+        };
+        let end = position + RECORD_HEADER + len;
+        if crc32(payload) != checksum {
+            if end == bytes.len() {
+                break;
+            }
+            return Err(damaged());
+        }
+        apply(db, payload).map_err(|message| {
+            snapshot::invalid(format!(
+                "journal record at byte {} does not apply: {}",
+                position, message
+            ))
+        })?;
+        position = end;
+    }
+    Ok(position)
+}
+
+fn apply(db: &mut Database, payload: &[u8]) -> Result<(), String> {
+    let mut decoder = Decoder::raw(payload);
+    match decoder.u8().map_err(|e| e.to_string())? {
+        kind @ (INSERT | RETRACT) => {
+            let atom = decode_atom(&mut decoder).map_err(|e| e.to_string())?;
+            if kind == INSERT {
+                db.add_atom(atom)?;
+            } else {
+                db.retract_atom(&atom);
+            }
+        }
+        RULE => {
+            let source = decoder.str().map_err(|e| e.to_string())?;
+            let rule = match parse_rule(source) {
+                Ok(("", rule)) => rule,
+                _ => return Err(format!("invalid rule `{}`", source)),
+            };
+            // Already there if the journal was compacted but not emptied
+            if !db.rules().iter().any(|r| r.to_string() == source) {
+                db.add_rule(rule)?;
+            }
+        }
+        kind => return Err(format!("unknown record kind {}", kind)),
+    }
+    decoder.finish().map_err(|e| e.to_string())
+}
+
+fn decode_atom(decoder: &mut Decoder) -> io::Result<Atom> {
+    let name = decoder.str()?.to_string();
+    let mut args = Vec::new();
+    for _ in 0..decoder.len()? {
+        args.push(Term::Const(decoder.value()?));
+    }
+    Ok(Atom::new(name, args))
+}
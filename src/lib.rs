@@ -1,3 +1,12 @@
 pub mod api;
+pub mod convert;
+pub mod diagnostics;
+pub mod evaluation;
+pub mod journal;
 pub mod parser;
+pub mod query_builder;
 pub mod query_engine;
+pub mod snapshot;
+pub mod storage;
+pub mod stratification;
+pub mod value;
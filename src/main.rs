@@ -1,4 +1,5 @@
-use dataloglite::query_engine::interpret;
+use dataloglite::api::Database;
+use dataloglite::query_engine::Engine;
 
 use clap::Parser;
 use std::fs;
@@ -7,28 +8,77 @@ use std::fs;
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Input file to parse
-    input_file: String,
-}
+    #[arg(required_unless_present = "load_snapshot")]
+    input_file: Option<String>,
 
-#[cfg(test)]
-mod tests {
-    // Empty test module kept for consistency
+    /// Start from the database saved in this snapshot
+    #[arg(long, value_name = "PATH")]
+    load_snapshot: Option<String>,
+
+    /// Save the database to this snapshot once the input has run
+    #[arg(long, value_name = "PATH")]
+    save_snapshot: Option<String>,
 }
 
 fn main() {
     let args = Args::parse();
-    let input = match fs::read_to_string(&args.input_file) {
-        Ok(content) => content,
-        Err(e) => {
-            eprintln!("Error reading file: {}", e);
+    let mut engine = match &args.load_snapshot {
+        Some(path) => match Database::load_snapshot(path) {
+            Ok(db) => Engine::with_database(db),
+            Err(e) => {
+                eprintln!("Error loading snapshot {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => Engine::new(),
+    };
+
+    // Statements with errors are skipped, but the run still fails
+    let mut failed = false;
+    if let Some(input_file) = &args.input_file {
+        let input = match fs::read_to_string(input_file) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Error reading file: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        if input.trim().is_empty() {
+            eprintln!("Error: Input file is empty");
             std::process::exit(1);
         }
-    };
 
-    if input.trim().is_empty() {
-        eprintln!("Error: Input file is empty");
+        match engine.interpret(&input, input_file, &mut std::io::stdout()) {
+            Ok(diagnostics) => {
+                for diagnostic in &diagnostics {
+                    eprintln!("{}", diagnostic);
+                }
+                failed = !diagnostics.is_empty();
+            }
+            Err(e) => {
+                eprintln!("Error writing output: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = &args.save_snapshot {
+        let db = engine.database_mut();
+        // Saved evaluated, so that loading it does not derive again
+        db.evaluate_rules();
+        if let Err(e) = db.save_snapshot(path) {
+            eprintln!("Error saving snapshot {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    if failed {
         std::process::exit(1);
     }
+}
 
-    interpret(&input, &mut std::io::stdout(), None);
+#[cfg(test)]
+mod tests {
+    // Empty test module kept for consistency
 }
@@ -1,32 +1,87 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use itertools::Itertools;
+
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_until},
-    character::complete::{alpha1, char, not_line_ending, space0},
-    combinator::{map, recognize, value},
-    multi::{many0, separated_list1},
+    character::complete::{char, digit1, none_of, not_line_ending, space0, space1},
+    combinator::{cut, map, opt, recognize, value},
+    error::{context, ErrorKind, ParseError},
+    multi::{fold_many0, many0},
     sequence::{delimited, pair, preceded, separated_pair, terminated},
-    IResult, Parser as NomParser,
+    Parser as NomParser,
 };
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-pub struct Fact {
+use crate::diagnostics::{IResult, Span, SyntaxError};
+use crate::value::Value;
+
+/// An argument of an atom: a variable such as `X`, `Who` or the anonymous `_`,
+/// a constant such as `"Bob"` or `42`, or a parameter of a prepared query
+/// such as `$child`
+#[derive(Debug, Clone, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum Term {
+    Var(String),
+    Const(Value),
+    /// Name of the parameter, without the `$`
+    Param(String),
+}
+
+// Nodes are compared and hashed without their span: the same fact read from
+// two places is still the same fact
+macro_rules! eq_without_span {
+    ($($node:ident { $($field:ident),+ })+) => {$(
+        impl PartialEq for $node {
+            fn eq(&self, other: &Self) -> bool {
+                $(self.$field == other.$field)&&+
+            }
+        }
+
+        impl Eq for $node {}
+
+        impl Hash for $node {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                $(self.$field.hash(state);)+
+            }
+        }
+    )+};
+}
+
+eq_without_span! {
+    Atom { name, args }
+    ConjunctiveQuery { data }
+    Comparison { left, operator, right }
+    QueryProjectionRelation { name, first, second }
+    QueryProjectionFact { name }
+    VariableBasedRelationFirstIsVar { name, variable, second }
+    VariableBasedRelationSecondIsVar { name, first, variable }
+}
+
+/// A predicate applied to an ordered list of arguments, such as
+/// `parent("Alice", "Bob")`, `born("Alice", 1970, "Paris")` or `raining`
+#[derive(Debug, Clone)]
+pub struct Atom {
     pub name: String,
-    pub first: String,
+    pub args: Vec<Term>,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum DatalogItem {
-    Fact(Fact),
-    Relation(Relation),
+    Atom(Atom),
     Rule(Rule),
     Query(Query),
+    // Only found in rule bodies
+    Comparison(Comparison),
+    // Only found in rule bodies, wraps an Atom
+    Negation(Box<DatalogItem>),
 }
 
 // TODO: review enum
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum NonQueryDatalogItem {
-    Fact(Fact),
-    Relation(Relation),
+    Atom(Atom),
     VariableBasedRelation(VariableBasedRelation),
     ConjunctiveQuery(ConjunctiveQuery),
     QueryProjectionRelation(QueryProjectionRelation),
@@ -34,148 +89,638 @@ pub enum NonQueryDatalogItem {
     Rule(Rule),
 }
 
-#[derive(Debug)]
+impl NonQueryDatalogItem {
+    /// The arguments of the atoms and comparisons of the query, in order
+    pub fn terms(&self) -> Vec<&Term> {
+        let mut terms = Vec::new();
+        match self {
+            NonQueryDatalogItem::Atom(atom) => terms.extend(&atom.args),
+            NonQueryDatalogItem::ConjunctiveQuery(query) => {
+                for item in &query.data {
+                    item.collect_terms(&mut terms);
+                }
+            }
+            NonQueryDatalogItem::QueryProjectionRelation(query) => {
+                terms.extend([&query.first, &query.second])
+            }
+            NonQueryDatalogItem::Rule(rule) => terms = rule.terms(),
+            NonQueryDatalogItem::VariableBasedRelation(_)
+            | NonQueryDatalogItem::QueryProjectionFact(_) => {}
+        }
+        terms
+    }
+
+    /// Replaces every parameter by its value in `values`, or fails with the
+    /// name of the first parameter that has none
+    pub fn bind_params(&mut self, values: &dyn Fn(&str) -> Option<Value>) -> Result<(), String> {
+        let mut terms: Vec<&mut Term> = Vec::new();
+        match self {
+            NonQueryDatalogItem::Atom(atom) => terms.extend(&mut atom.args),
+            NonQueryDatalogItem::ConjunctiveQuery(query) => {
+                for item in &mut query.data {
+                    item.collect_terms_mut(&mut terms);
+                }
+            }
+            _ => {}
+        }
+        for term in terms {
+            if let Term::Param(name) = term {
+                match values(name) {
+                    Some(value) => *term = Term::Const(value),
+                    None => return Err(name.clone()),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl QueryProjection {
+    fn collect_terms<'a>(&'a self, terms: &mut Vec<&'a Term>) {
+        match self {
+            QueryProjection::Atom(atom) => terms.extend(&atom.args),
+            QueryProjection::Comparison(comparison) => {
+                terms.extend([&comparison.left, &comparison.right])
+            }
+            QueryProjection::Negation(item) => item.collect_terms(terms),
+        }
+    }
+
+    fn collect_terms_mut<'a>(&'a mut self, terms: &mut Vec<&'a mut Term>) {
+        match self {
+            QueryProjection::Atom(atom) => terms.extend(&mut atom.args),
+            QueryProjection::Comparison(comparison) => {
+                terms.extend([&mut comparison.left, &mut comparison.right])
+            }
+            QueryProjection::Negation(item) => item.collect_terms_mut(terms),
+        }
+    }
+}
+
+impl From<ConjunctiveQuery> for NonQueryDatalogItem {
+    fn from(query: ConjunctiveQuery) -> Self {
+        NonQueryDatalogItem::ConjunctiveQuery(query)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Query {
     pub data: NonQueryDatalogItem,
+    pub span: Span,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone)]
 pub struct ConjunctiveQuery {
     pub data: Vec<QueryProjection>,
+    pub span: Span,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-pub struct Relation {
-    pub name: String,
-    pub first: String,
-    pub second: String,
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum QueryProjection {
+    Atom(Atom),
+    Comparison(Comparison),
+    Negation(Box<QueryProjection>),
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
-pub enum QueryProjection {
-    QueryProjectionRelation(QueryProjectionRelation),
-    QueryProjectionFact(QueryProjectionFact),
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ComparisonOperator {
+    Equal,
+    NotEqual,
+    LessThan,
+    LessOrEqual,
+    GreaterThan,
+    GreaterOrEqual,
+}
+
+/// A built-in comparison such as `X != Y`, `X < "Bob"` or `Y >= 1970`
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub left: Term,
+    pub operator: ComparisonOperator,
+    pub right: Term,
+    pub span: Span,
+}
+
+impl ComparisonOperator {
+    /// Applies the operator to two values, ordered by `Value::compare`
+    pub fn compare(&self, left: &Value, right: &Value) -> bool {
+        let ordering = left.compare(right);
+        match self {
+            ComparisonOperator::Equal => ordering.is_eq(),
+            ComparisonOperator::NotEqual => ordering.is_ne(),
+            ComparisonOperator::LessThan => ordering.is_lt(),
+            ComparisonOperator::LessOrEqual => ordering.is_le(),
+            ComparisonOperator::GreaterThan => ordering.is_gt(),
+            ComparisonOperator::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+impl Comparison {
+    /// Names of the variables used by the comparison
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        [&self.left, &self.right]
+            .into_iter()
+            .filter_map(Term::as_var)
+    }
+}
+
+impl ConjunctiveQuery {
+    /// Names of the variables bound by the query, in order of first appearance
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables: Vec<&str> = Vec::new();
+        for item in &self.data {
+            if let QueryProjection::Atom(atom) = item {
+                for variable in atom.variables() {
+                    if !variables.contains(&variable) {
+                        variables.push(variable);
+                    }
+                }
+            }
+        }
+        variables
+    }
+}
+
+impl Term {
+    /// Name of the anonymous variable; each of its occurrences matches anything
+    pub const ANONYMOUS: &'static str = "_";
+
+    pub fn var(name: &str) -> Self {
+        Term::Var(name.to_string())
+    }
+
+    pub fn constant(value: impl Into<Value>) -> Self {
+        Term::Const(value.into())
+    }
+
+    pub fn param(name: &str) -> Self {
+        Term::Param(name.to_string())
+    }
+
+    /// Name of the variable, `None` for constants and for `_`
+    pub fn as_var(&self) -> Option<&str> {
+        match self {
+            Term::Var(name) if name != Term::ANONYMOUS => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn as_value(&self) -> Option<&Value> {
+        match self {
+            Term::Const(value) => Some(value),
+            Term::Var(_) | Term::Param(_) => None,
+        }
+    }
+
+    /// Name of the parameter, without the `$`
+    pub fn as_param(&self) -> Option<&str> {
+        match self {
+            Term::Param(name) => Some(name),
+            Term::Var(_) | Term::Const(_) => None,
+        }
+    }
+}
+
+impl From<Value> for Term {
+    fn from(value: Value) -> Self {
+        Term::Const(value)
+    }
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Var(name) => write!(f, "{}", name),
+            Term::Const(value) => write!(f, "{}", value.to_source()),
+            Term::Param(name) => write!(f, "${}", name),
+        }
+    }
+}
+
+impl Atom {
+    /// An atom that was not read from a source file
+    pub fn new(name: impl Into<String>, args: Vec<Term>) -> Self {
+        Atom {
+            name: name.into(),
+            args,
+            span: Span::default(),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Names of the variables of the atom in order, without `_`
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.args.iter().filter_map(Term::as_var)
+    }
+
+    /// The argument values, `None` if the atom has a variable
+    pub fn values(&self) -> Option<Vec<&Value>> {
+        self.args.iter().map(Term::as_value).collect()
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.args.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}({})", self.name, self.args.iter().format(", "))
+        }
+    }
+}
+
+impl fmt::Display for ComparisonOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            ComparisonOperator::Equal => "=",
+            ComparisonOperator::NotEqual => "!=",
+            ComparisonOperator::LessThan => "<",
+            ComparisonOperator::LessOrEqual => "<=",
+            ComparisonOperator::GreaterThan => ">",
+            ComparisonOperator::GreaterOrEqual => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.operator, self.right)
+    }
+}
+
+// As written in a program, so that it parses back to the same rule
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} :- ", self.head)?;
+        for (position, item) in self.definition.relations.iter().enumerate() {
+            if position > 0 {
+                write!(f, ", ")?;
+            }
+            write_body_item(f, item)?;
+        }
+        write!(f, ".")
+    }
+}
+
+fn write_body_item(f: &mut fmt::Formatter<'_>, item: &DatalogItem) -> fmt::Result {
+    match item {
+        DatalogItem::Atom(atom) => write!(f, "{}", atom),
+        DatalogItem::Comparison(comparison) => write!(f, "{}", comparison),
+        DatalogItem::Negation(inner) => {
+            write!(f, "!")?;
+            write_body_item(f, inner)
+        }
+        DatalogItem::Rule(_) | DatalogItem::Query(_) => unreachable!("not found in rule bodies"),
+    }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone)]
 pub struct QueryProjectionRelation {
     pub name: String,
-    pub first: String,
-    pub second: String,
+    pub first: Term,
+    pub second: Term,
+    pub span: Span,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone)]
 pub struct QueryProjectionFact {
     pub name: String,
+    pub span: Span,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum VariableBasedRelation {
     VariableBasedRelationFirstIsVar(VariableBasedRelationFirstIsVar),
     VariableBasedRelationSecondIsVar(VariableBasedRelationSecondIsVar),
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone)]
 pub struct VariableBasedRelationFirstIsVar {
     pub name: String,
-    pub second: String,
+    pub variable: String,
+    pub second: Value,
+    pub span: Span,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone)]
 pub struct VariableBasedRelationSecondIsVar {
     pub name: String,
-    pub first: String,
+    pub first: Value,
+    pub variable: String,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Rule {
-    pub name: String,
-    pub first: String,
-    pub second: String,
+    pub head: Atom,
     pub definition: RuleDefinition,
+    pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RuleDefinition {
     pub relations: Vec<DatalogItem>,
+    pub span: Span,
+}
+
+impl DatalogItem {
+    /// Where the item was read from
+    pub fn span(&self) -> Span {
+        match self {
+            DatalogItem::Atom(atom) => atom.span,
+            DatalogItem::Rule(rule) => rule.span,
+            DatalogItem::Query(query) => query.span,
+            DatalogItem::Comparison(comparison) => comparison.span,
+            DatalogItem::Negation(item) => item.span(),
+        }
+    }
+
+    /// The arguments of the item and of the items inside it, in order
+    pub fn terms(&self) -> Vec<&Term> {
+        match self {
+            DatalogItem::Atom(atom) => atom.args.iter().collect(),
+            DatalogItem::Rule(rule) => rule.terms(),
+            DatalogItem::Query(query) => query.data.terms(),
+            DatalogItem::Comparison(comparison) => vec![&comparison.left, &comparison.right],
+            DatalogItem::Negation(item) => item.terms(),
+        }
+    }
+
+    /// Locates the spans of the item, and of the nodes in it, in `source`,
+    /// the text the item was parsed from. `parse_datalog` does this already.
+    pub fn locate(&mut self, source: &str) {
+        match self {
+            DatalogItem::Atom(atom) => atom.span.locate(source),
+            DatalogItem::Rule(rule) => rule.locate(source),
+            DatalogItem::Query(query) => query.locate(source),
+            DatalogItem::Comparison(comparison) => comparison.span.locate(source),
+            DatalogItem::Negation(item) => item.locate(source),
+        }
+    }
+}
+
+impl Rule {
+    /// The arguments of the head and of the body items, in order
+    pub fn terms(&self) -> Vec<&Term> {
+        self.head
+            .args
+            .iter()
+            .chain(
+                self.definition
+                    .relations
+                    .iter()
+                    .flat_map(DatalogItem::terms),
+            )
+            .collect()
+    }
+
+    /// See `DatalogItem::locate`
+    pub fn locate(&mut self, source: &str) {
+        self.span.locate(source);
+        self.head.span.locate(source);
+        self.definition.span.locate(source);
+        for item in &mut self.definition.relations {
+            item.locate(source);
+        }
+    }
+}
+
+impl Query {
+    /// See `DatalogItem::locate`
+    pub fn locate(&mut self, source: &str) {
+        self.span.locate(source);
+        match &mut self.data {
+            NonQueryDatalogItem::Atom(atom) => atom.span.locate(source),
+            NonQueryDatalogItem::VariableBasedRelation(
+                VariableBasedRelation::VariableBasedRelationFirstIsVar(relation),
+            ) => relation.span.locate(source),
+            NonQueryDatalogItem::VariableBasedRelation(
+                VariableBasedRelation::VariableBasedRelationSecondIsVar(relation),
+            ) => relation.span.locate(source),
+            NonQueryDatalogItem::ConjunctiveQuery(query) => {
+                query.span.locate(source);
+                for item in &mut query.data {
+                    item.locate(source);
+                }
+            }
+            NonQueryDatalogItem::QueryProjectionRelation(query) => query.span.locate(source),
+            NonQueryDatalogItem::QueryProjectionFact(query) => query.span.locate(source),
+            NonQueryDatalogItem::Rule(rule) => rule.locate(source),
+        }
+    }
+}
+
+impl QueryProjection {
+    fn locate(&mut self, source: &str) {
+        match self {
+            QueryProjection::Atom(atom) => atom.span.locate(source),
+            QueryProjection::Comparison(comparison) => comparison.span.locate(source),
+            QueryProjection::Negation(item) => item.locate(source),
+        }
+    }
+}
+
+// One character of a quoted string: `\"`, `\\`, `\n`, `\t` or anything but `"` and `\`
+fn parse_string_char(input: &str) -> IResult<&str, char> {
+    alt((
+        preceded(
+            char('\\'),
+            alt((
+                value('"', char('"')),
+                value('\\', char('\\')),
+                value('\n', char('n')),
+                value('\t', char('t')),
+            )),
+        ),
+        none_of("\"\\\n"),
+    ))
+    .parse(input)
 }
 
 pub fn parse_quoted_string(input: &str) -> IResult<&str, String> {
-    delimited(char('"'), map(alpha1, |s: &str| s.to_string()), char('"')).parse(input)
+    context(
+        "a string",
+        delimited(
+            char('"'),
+            fold_many0(parse_string_char, String::new, |mut s, c| {
+                s.push(c);
+                s
+            }),
+            char('"'),
+        ),
+    )
+    .parse(input)
 }
 
-pub fn parse_variable(input: &str) -> IResult<&str, String> {
-    map(
-        recognize(preceded(
-            nom::character::complete::satisfy(|c| c.is_ascii_uppercase()),
-            nom::character::complete::alphanumeric0,
+// `42`, `-7`, `3.14` or `-0.5`; a dot without digits after it ends the statement
+pub fn parse_number(input: &str) -> IResult<&str, Value> {
+    let (rest, text) = context(
+        "a number",
+        recognize(pair(
+            pair(opt(char('-')), digit1),
+            opt(pair(char('.'), digit1)),
         )),
-        |s: &str| s.to_string(),
     )
-    .parse(input)
+    .parse(input)?;
+    let number = if text.contains('.') {
+        text.parse().map(Value::Float).ok()
+    } else {
+        text.parse().map(Value::Integer).ok()
+    };
+    match number {
+        Some(number) => Ok((rest, number)),
+        // Integers that do not fit in 64 bits
+        None => Err(nom::Err::Error(SyntaxError::from_error_kind(
+            input,
+            ErrorKind::Digit,
+        ))),
+    }
 }
 
-pub fn parse_argument(input: &str) -> IResult<&str, String> {
-    alt((parse_quoted_string, parse_variable)).parse(input)
+// A constant: a quoted string or a number
+pub fn parse_value(input: &str) -> IResult<&str, Value> {
+    alt((map(parse_quoted_string, Value::String), parse_number)).parse(input)
 }
 
-pub fn parse_x_or_y(input: &str) -> IResult<&str, String> {
+// `X`, `Who`, `Parent_1`: an uppercase letter followed by letters, digits or `_`
+pub fn parse_variable(input: &str) -> IResult<&str, String> {
+    context(
+        "a variable",
+        map(
+            recognize(pair(
+                nom::character::complete::satisfy(|c| c.is_ascii_uppercase()),
+                many0(alt((
+                    nom::character::complete::satisfy(|c| c.is_ascii_alphanumeric()),
+                    nom::character::complete::char('_'),
+                ))),
+            )),
+            |s: &str| s.to_string(),
+        ),
+    )
+    .parse(input)
+}
+
+// A named variable or `_`
+pub fn parse_variable_or_anonymous(input: &str) -> IResult<&str, String> {
     alt((
-        nom::character::complete::char('X'),
-        nom::character::complete::char('Y'),
+        parse_variable,
+        context("`_`", map(tag(Term::ANONYMOUS), |s: &str| s.to_string())),
     ))
-    .map(|c| c.to_string())
     .parse(input)
 }
 
-pub fn parse_x_y_underscore(input: &str) -> IResult<&str, String> {
+// `$child`, `$min_age`: a value given when a prepared query runs
+pub fn parse_parameter(input: &str) -> IResult<&str, String> {
+    context(
+        "a parameter",
+        preceded(
+            char('$'),
+            map(
+                recognize(pair(
+                    nom::character::complete::satisfy(|c| c.is_ascii_alphabetic() || c == '_'),
+                    many0(alt((
+                        nom::character::complete::satisfy(|c| c.is_ascii_alphanumeric()),
+                        nom::character::complete::char('_'),
+                    ))),
+                )),
+                |s: &str| s.to_string(),
+            ),
+        ),
+    )
+    .parse(input)
+}
+
+pub fn parse_argument(input: &str) -> IResult<&str, Term> {
     alt((
-        nom::character::complete::char('X'),
-        nom::character::complete::char('Y'),
-        nom::character::complete::char('_'),
+        map(parse_value, Term::Const),
+        map(parse_variable_or_anonymous, Term::Var),
+        map(parse_parameter, Term::Param),
     ))
-    .map(|c| c.to_string())
     .parse(input)
 }
 
 pub fn parse_name(input: &str) -> IResult<&str, String> {
-    map(
-        recognize(pair(
-            nom::character::complete::satisfy(|c| c.is_ascii_lowercase()),
-            many0(alt((
-                nom::character::complete::satisfy(|c| c.is_ascii_alphanumeric()),
-                nom::character::complete::char('_'),
-            ))),
-        )),
-        |s: &str| s.to_string(),
+    context(
+        "a predicate name",
+        map(
+            recognize(pair(
+                nom::character::complete::satisfy(|c| c.is_ascii_lowercase()),
+                many0(alt((
+                    nom::character::complete::satisfy(|c| c.is_ascii_alphanumeric()),
+                    nom::character::complete::char('_'),
+                ))),
+            )),
+            |s: &str| s.to_string(),
+        ),
     )
     .parse(input)
 }
 
-pub fn parse_relation(input: &str) -> IResult<&str, Relation> {
+/// Checks that all of `name` is a predicate name, for names given from Rust
+/// instead of parsed
+pub fn check_name(name: &str) -> Result<(), String> {
+    match parse_name(name) {
+        Ok(("", _)) => Ok(()),
+        _ => Err(format!("`{}` is not a predicate name", name)),
+    }
+}
+
+// `name`, `name()` or `name(arg, ...)`, each argument read by `argument`
+fn parse_atom_with<'a>(
+    input: &'a str,
+    argument: fn(&'a str) -> IResult<&'a str, Term>,
+) -> IResult<&'a str, Atom> {
+    let start = input;
     let (input, name) = parse_name(input)?;
-    let (input, _) = char('(')(input)?;
-    let (input, (first, second)) = separated_pair(
-        parse_quoted_string,
-        terminated(char(','), space0),
-        parse_quoted_string,
-    )
-    .parse(input)?;
-    let (input, _) = char(')')(input)?;
-    let (input, _) = char('.')(input)?;
+    let (mut input, open) = opt(char('(')).parse(input)?;
+    let mut args = Vec::new();
+    if open.is_some() {
+        if let Ok((rest, _)) = char::<_, SyntaxError<&str>>(')')(input) {
+            let span = Span::between(start, rest);
+            return Ok((rest, Atom { name, args, span }));
+        }
+        loop {
+            let (rest, arg) = argument(input)?;
+            args.push(arg);
+            let (rest, close) = context(
+                "`,` or `)`",
+                alt((value(false, char(',')), value(true, char(')')))),
+            )
+            .parse(rest)?;
+            if close {
+                input = rest;
+                break;
+            }
+            (input, _) = space0(rest)?;
+        }
+    }
 
-    Ok((
-        input,
-        Relation {
-            name,
-            first,
-            second,
-        },
-    ))
+    let span = Span::between(start, input);
+    Ok((input, Atom { name, args, span }))
+}
+
+// Atom whose arguments are all constants
+pub fn parse_ground_atom(input: &str) -> IResult<&str, Atom> {
+    parse_atom_with(input, |input| map(parse_value, Term::Const).parse(input))
+}
+
+// Atom whose arguments may be variables, as found in rule bodies
+pub fn parse_atom_with_vars(input: &str) -> IResult<&str, Atom> {
+    parse_atom_with(input, parse_argument)
+}
+
+// Atom statement, terminated by a dot
+pub fn parse_atom(input: &str) -> IResult<&str, Atom> {
+    terminated(parse_atom_with_vars, char('.')).parse(input)
 }
 
 pub fn parse_variable_based_relation(input: &str) -> IResult<&str, VariableBasedRelation> {
-    let variable_char = "X";
+    let start = input;
     let (input, name) = parse_name(input)?;
     let (input, _) = char('(')(input)?;
     let (input, (first, second)) = separated_pair(
@@ -186,75 +731,88 @@ pub fn parse_variable_based_relation(input: &str) -> IResult<&str, VariableBased
     .parse(input)?;
     let (input, _) = char(')')(input)?;
     let (input, _) = char('.')(input)?;
+    let span = Span::between(start, input);
 
-    match (&first == variable_char, &second == variable_char) {
-        (true, false) => Ok((
+    match (
+        first.as_var().map(String::from),
+        second.as_var().map(String::from),
+        first,
+        second,
+    ) {
+        (Some(variable), None, _, Term::Const(second)) => Ok((
             input,
             VariableBasedRelation::VariableBasedRelationFirstIsVar(
-                VariableBasedRelationFirstIsVar { name, second },
+                VariableBasedRelationFirstIsVar {
+                    name,
+                    variable,
+                    second,
+                    span,
+                },
             ),
         )),
-        (false, true) => Ok((
+        (None, Some(variable), Term::Const(first), _) => Ok((
             input,
             VariableBasedRelation::VariableBasedRelationSecondIsVar(
-                VariableBasedRelationSecondIsVar { name, first },
+                VariableBasedRelationSecondIsVar {
+                    name,
+                    first,
+                    variable,
+                    span,
+                },
             ),
         )),
-        _ => Err(nom::Err::Error(nom::error::Error::new(
+        _ => Err(nom::Err::Error(SyntaxError::from_error_kind(
             input,
-            nom::error::ErrorKind::Fail,
+            ErrorKind::Fail,
         ))),
     }
 }
 
+// `?parent(X, _).` or `?parent(_, Child).`: exactly one side is anonymous
 pub fn parse_query_projection_relation(input: &str) -> IResult<&str, QueryProjectionRelation> {
-    let unknown_char = "_";
+    let start = input;
     let (input, name) = parse_name(input)?;
     let (input, _) = char('(')(input)?;
     let (input, (first, second)) = separated_pair(
-        parse_x_y_underscore,
+        map(parse_variable_or_anonymous, Term::Var),
         terminated(char(','), space0),
-        parse_x_y_underscore,
+        map(parse_variable_or_anonymous, Term::Var),
     )
     .parse(input)?;
     let (input, _) = char(')')(input)?;
     let (input, _) = char('.')(input)?;
 
-    match (&first == unknown_char, &second == unknown_char) {
-        (true, false) => Ok((
-            input,
-            QueryProjectionRelation {
-                name,
-                first: "_".to_string(),
-                second: second,
-            },
-        )),
-        (false, true) => Ok((
+    if first.as_var().is_some() == second.as_var().is_some() {
+        return Err(nom::Err::Error(SyntaxError::from_error_kind(
             input,
-            QueryProjectionRelation {
-                name,
-                first: first,
-                second: "_".to_string(),
-            },
-        )),
-        _ => Err(nom::Err::Error(nom::error::Error::new(
-            input,
-            nom::error::ErrorKind::Fail,
-        ))),
+            ErrorKind::Fail,
+        )));
     }
+    Ok((
+        input,
+        QueryProjectionRelation {
+            name,
+            first,
+            second,
+            span: Span::between(start, input),
+        },
+    ))
 }
 
 pub fn parse_query_projection_fact(input: &str) -> IResult<&str, QueryProjectionFact> {
+    let start = input;
     let (input, name) = parse_name(input)?;
     let (input, _) = char('(')(input)?;
-    let (input, _) = char('_')(input)?;
+    let (input, _) = tag(Term::ANONYMOUS)(input)?;
     let (input, _) = char(')')(input)?;
     let (input, _) = char('.')(input)?;
 
-    Ok((input, QueryProjectionFact { name }))
+    let span = Span::between(start, input);
+    Ok((input, QueryProjectionFact { name, span }))
 }
 
 pub fn parse_query(input: &str) -> IResult<&str, Query> {
+    let start = input;
     let (input, _) = char('?')(input)?;
     let (input, item) = alt((
         // TODO: all projections in one?
@@ -266,14 +824,16 @@ pub fn parse_query(input: &str) -> IResult<&str, Query> {
             parse_query_projection_relation,
             NonQueryDatalogItem::QueryProjectionRelation,
         ),
-        map(parse_fact, NonQueryDatalogItem::Fact),
         map(
             parse_variable_based_relation,
             NonQueryDatalogItem::VariableBasedRelation,
         ),
         // order is important
         // after parse_variable_based_relation
-        map(parse_relation, NonQueryDatalogItem::Relation),
+        map(
+            terminated(parse_ground_atom, char('.')),
+            NonQueryDatalogItem::Atom,
+        ),
         // conjunctive query after relation
         // they look very similar
         map(
@@ -283,124 +843,145 @@ pub fn parse_query(input: &str) -> IResult<&str, Query> {
     ))
     .parse(input)?;
 
-    Ok((input, Query { data: item }))
+    let span = Span::between(start, input);
+    Ok((input, Query { data: item, span }))
 }
 
-pub fn parse_fact(input: &str) -> IResult<&str, Fact> {
-    let (input, name) = parse_name(input)?;
-    let (input, _) = char('(')(input)?;
-    let (input, first) = parse_argument(input)?;
-    let (input, _) = char(')')(input)?;
-    let (input, _) = char('.')(input)?;
-
-    Ok((input, Fact { name, first }))
+pub fn parse_comparison_operator(input: &str) -> IResult<&str, ComparisonOperator> {
+    // Two-character operators first, so `<=` is not read as `<`
+    let operators = alt((
+        value(ComparisonOperator::NotEqual, tag("!=")),
+        value(ComparisonOperator::LessOrEqual, tag("<=")),
+        value(ComparisonOperator::GreaterOrEqual, tag(">=")),
+        value(ComparisonOperator::Equal, tag("=")),
+        value(ComparisonOperator::LessThan, tag("<")),
+        value(ComparisonOperator::GreaterThan, tag(">")),
+    ));
+    context("a comparison operator", operators).parse(input)
 }
 
-pub fn parse_relation_or_fact_with_vars(input: &str) -> IResult<&str, DatalogItem> {
+// A constant, a named variable or a parameter; `_` can not be compared
+pub fn parse_comparison_operand(input: &str) -> IResult<&str, Term> {
     alt((
-        map(parse_relation_with_vars, DatalogItem::Relation),
-        map(parse_fact_with_var, DatalogItem::Fact),
+        map(parse_value, Term::Const),
+        map(parse_variable, Term::Var),
+        map(parse_parameter, Term::Param),
     ))
     .parse(input)
 }
 
-pub fn parse_relation_with_vars(input: &str) -> IResult<&str, Relation> {
-    let (input, name) = parse_name(input)?;
-    let (input, _) = char('(')(input)?;
-    let (input, (first, second)) = separated_pair(
-        parse_argument,
-        terminated(char(','), space0),
-        parse_argument,
-    )
-    .parse(input)?;
-    let (input, _) = char(')')(input)?;
+pub fn parse_comparison(input: &str) -> IResult<&str, Comparison> {
+    let start = input;
+    let (input, left) = parse_comparison_operand(input)?;
+    let (input, operator) = delimited(space0, parse_comparison_operator, space0).parse(input)?;
+    let (input, right) = parse_comparison_operand(input)?;
 
     Ok((
         input,
-        Relation {
-            name,
-            first,
-            second,
+        Comparison {
+            left,
+            operator,
+            right,
+            span: Span::between(start, input),
         },
     ))
 }
 
-pub fn parse_fact_with_var(input: &str) -> IResult<&str, Fact> {
-    let (input, name) = parse_name(input)?;
-    let (input, _) = char('(')(input)?;
-    let (input, first) = parse_argument(input)?;
-    let (input, _) = char(')')(input)?;
+// `!male(X)` or `not male(X)`
+pub fn parse_negation(input: &str) -> IResult<&str, DatalogItem> {
+    let (input, _) =
+        alt((terminated(tag("!"), space0), terminated(tag("not"), space1))).parse(input)?;
+    let (input, atom) = parse_atom_with_vars(input)?;
 
-    Ok((input, Fact { name, first }))
+    Ok((
+        input,
+        DatalogItem::Negation(Box::new(DatalogItem::Atom(atom))),
+    ))
 }
 
-pub fn parse_rule_definition(input: &str) -> IResult<&str, RuleDefinition> {
-    let (input, relations) = separated_list1(
+pub fn parse_body_item(input: &str) -> IResult<&str, DatalogItem> {
+    alt((
+        parse_negation,
+        map(parse_atom_with_vars, DatalogItem::Atom),
+        map(parse_comparison, DatalogItem::Comparison),
+    ))
+    .parse(input)
+}
+
+// Body items separated by commas; an item must follow every comma
+fn parse_body_items(input: &str) -> IResult<&str, Vec<DatalogItem>> {
+    let (input, first) = parse_body_item(input)?;
+    let (input, rest) = many0(preceded(
         terminated(char(','), space0),
-        parse_relation_or_fact_with_vars,
-    )
+        cut(parse_body_item),
+    ))
     .parse(input)?;
 
-    Ok((input, RuleDefinition { relations }))
+    let mut items = vec![first];
+    items.extend(rest);
+    Ok((input, items))
+}
+
+pub fn parse_rule_definition(input: &str) -> IResult<&str, RuleDefinition> {
+    let start = input;
+    let (input, relations) = parse_body_items(input)?;
+    let span = Span::between(start, input);
+
+    Ok((input, RuleDefinition { relations, span }))
 }
 
 pub fn parse_rule(input: &str) -> IResult<&str, Rule> {
-    let (input, name) = parse_name(input)?;
-    let (input, _) = char('(')(input)?;
-    let (input, first) = parse_argument(input)?;
-    let (input, _) = terminated(char(','), space0).parse(input)?;
-    let (input, second) = parse_argument(input)?;
-    let (input, _) = char(')')(input)?;
-    let (input, _) = delimited(space0, tag(":-"), space0).parse(input)?;
-    let (input, definition) = parse_rule_definition(input)?;
-    let (input, _) = char('.')(input)?;
+    let start = input;
+    let (input, head) = parse_atom_with_vars(input)?;
+    let (input, _) = delimited(space0, context("`:-`", tag(":-")), space0).parse(input)?;
+    // After `:-` this can only be a rule, so errors are not backtracked
+    let (input, definition) = cut(parse_rule_definition).parse(input)?;
+    let (input, _) = cut(context("`,` or `.`", char('.'))).parse(input)?;
 
+    let span = Span::between(start, input);
     Ok((
         input,
         Rule {
-            name,
-            first,
-            second,
+            head,
             definition,
+            span,
         },
     ))
 }
 
+fn to_query_projection(item: DatalogItem) -> Option<QueryProjection> {
+    match item {
+        DatalogItem::Atom(atom) => Some(QueryProjection::Atom(atom)),
+        DatalogItem::Comparison(comparison) => Some(QueryProjection::Comparison(comparison)),
+        DatalogItem::Negation(item) => {
+            to_query_projection(*item).map(|q| QueryProjection::Negation(Box::new(q)))
+        }
+        DatalogItem::Rule(_) | DatalogItem::Query(_) => None,
+    }
+}
+
 pub fn parse_conjunctive_query(input: &str) -> IResult<&str, ConjunctiveQuery> {
-    print!("Parsing conjunctive query: {}", input);
-    let (input, data) = separated_list1(
-        terminated(char(','), space0),
-        parse_relation_or_fact_with_vars,
-    )
-    .parse(input)?;
+    let start = input;
+    let (input, data) = parse_body_items(input)?;
 
-    let (input, _) = char('.')(input)?;
+    let (input, _) = context("`,` or `.`", char('.')).parse(input)?;
 
-    let mut new_data = Vec::new();
-    for item in data {
-        if let DatalogItem::Fact(fact) = &item {
-            new_data.push(QueryProjection::QueryProjectionFact(QueryProjectionFact {
-                name: fact.name.clone(),
-            }));
-        } else if let DatalogItem::Relation(rel) = &item {
-            new_data.push(QueryProjection::QueryProjectionRelation(
-                QueryProjectionRelation {
-                    name: rel.name.clone(),
-                    first: rel.first.clone(),
-                    second: rel.second.clone(),
-                },
-            ));
-        }
-    }
+    let new_data = data.into_iter().filter_map(to_query_projection).collect();
 
-    Ok((input, ConjunctiveQuery { data: new_data }))
+    let span = Span::between(start, input);
+    Ok((
+        input,
+        ConjunctiveQuery {
+            data: new_data,
+            span,
+        },
+    ))
 }
 
 pub fn parse_datalog_item(input: &str) -> IResult<&str, DatalogItem> {
     alt((
         map(parse_rule, DatalogItem::Rule),
-        map(parse_fact, DatalogItem::Fact),
-        map(parse_relation, DatalogItem::Relation),
+        map(parse_atom, DatalogItem::Atom),
         map(parse_query, DatalogItem::Query),
     ))
     .parse(input)
@@ -426,19 +1007,26 @@ pub fn parse_comment(input: &str) -> IResult<&str, ()> {
     alt((parse_line_comment, parse_block_comment)).parse(input)
 }
 
-pub fn parse_datalog(input: &str) -> IResult<&str, Vec<DatalogItem>> {
-    let (input, _) = many0(alt((
-        value((), nom::character::complete::multispace1),
-        parse_comment,
-    )))
-    .parse(input)?;
-
-    many0(terminated(
-        parse_datalog_item,
+// Whitespace and comments between statements
+pub fn parse_whitespace(input: &str) -> IResult<&str, ()> {
+    value(
+        (),
         many0(alt((
             value((), nom::character::complete::multispace1),
             parse_comment,
         ))),
-    ))
+    )
     .parse(input)
 }
+
+/// Parses a whole program, with the spans of its items located in `source`
+pub fn parse_datalog(source: &str) -> IResult<&str, Vec<DatalogItem>> {
+    let (input, _) = parse_whitespace(source)?;
+
+    let (input, mut items) =
+        many0(terminated(parse_datalog_item, parse_whitespace)).parse(input)?;
+    for item in &mut items {
+        item.locate(source);
+    }
+    Ok((input, items))
+}
@@ -0,0 +1,161 @@
+use crate::api::{Database, QueryResult};
+use crate::diagnostics::{IResult, Span};
+use crate::parser::{
+    check_name, parse_variable, Atom, Comparison, ComparisonOperator, ConjunctiveQuery,
+    QueryProjection, Term,
+};
+use crate::value::Value;
+
+/// Builds a conjunctive query in Rust instead of in Datalog text, so values
+/// never need quoting:
+///
+/// ```
+/// use dataloglite::query_builder::QueryBuilder;
+///
+/// // ?parent(X, "Bob"), !male(X).
+/// let query = QueryBuilder::atom("parent")
+///     .var("X")
+///     .constant("Bob")
+///     .and(QueryBuilder::not("male").var("X"))
+///     .build()
+///     .unwrap();
+/// assert_eq!(query.variables(), ["X"]);
+/// ```
+///
+/// Names are checked by `build` against the grammar, so a built query is one
+/// the parser could have produced.
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    items: Vec<QueryProjection>,
+    // First misuse, such as an argument added after a comparison
+    error: Option<String>,
+}
+
+impl QueryBuilder {
+    fn new(item: QueryProjection) -> Self {
+        QueryBuilder {
+            items: vec![item],
+            error: None,
+        }
+    }
+
+    /// Starts a query with the atom `name`; arguments follow with `var`,
+    /// `anonymous` and `constant`
+    pub fn atom(name: &str) -> Self {
+        QueryBuilder::new(QueryProjection::Atom(Atom::new(name, Vec::new())))
+    }
+
+    /// Like `atom`, for an atom that must not hold, as `!male(X)`
+    pub fn not(name: &str) -> Self {
+        QueryBuilder::new(QueryProjection::Negation(Box::new(QueryProjection::Atom(
+            Atom::new(name, Vec::new()),
+        ))))
+    }
+
+    /// Starts a query with a comparison, as `X != "Bob"`
+    pub fn compare(left: Term, operator: ComparisonOperator, right: Term) -> Self {
+        QueryBuilder::new(QueryProjection::Comparison(Comparison {
+            left,
+            operator,
+            right,
+            span: Span::default(),
+        }))
+    }
+
+    /// Adds the variable `name` to the last atom
+    pub fn var(self, name: &str) -> Self {
+        self.arg(Term::var(name))
+    }
+
+    /// Adds `_` to the last atom
+    pub fn anonymous(self) -> Self {
+        self.arg(Term::var(Term::ANONYMOUS))
+    }
+
+    /// Adds a constant to the last atom
+    pub fn constant(self, value: impl Into<Value>) -> Self {
+        self.arg(Term::Const(value.into()))
+    }
+
+    fn arg(mut self, term: Term) -> Self {
+        let atom = match self.items.last_mut() {
+            Some(QueryProjection::Atom(atom)) => Some(atom),
+            Some(QueryProjection::Negation(item)) => match item.as_mut() {
+                QueryProjection::Atom(atom) => Some(atom),
+                _ => None,
+            },
+            _ => None,
+        };
+        match atom {
+            Some(atom) => atom.args.push(term),
+            None => {
+                self.error
+                    .get_or_insert_with(|| format!("argument {} follows a comparison", term));
+            }
+        }
+        self
+    }
+
+    /// Adds the items of `other` to the query
+    pub fn and(mut self, other: QueryBuilder) -> Self {
+        self.items.extend(other.items);
+        if self.error.is_none() {
+            self.error = other.error;
+        }
+        self
+    }
+
+    /// The query, as `parse_query` would have read it
+    pub fn build(self) -> Result<ConjunctiveQuery, String> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        for item in &self.items {
+            check_item(item)?;
+        }
+        Ok(ConjunctiveQuery {
+            data: self.items,
+            span: Span::default(),
+        })
+    }
+
+    /// Builds the query and answers it on `db`, as `Database::query` would
+    pub fn execute(self, db: &mut Database) -> Result<QueryResult, String> {
+        db.execute(self.build()?)
+    }
+}
+
+// Whether all of `input` is read by `parser`
+fn is_whole<T>(input: &str, parser: fn(&str) -> IResult<&str, T>) -> bool {
+    matches!(parser(input), Ok(("", _)))
+}
+
+fn check_item(item: &QueryProjection) -> Result<(), String> {
+    match item {
+        QueryProjection::Atom(atom) => {
+            check_name(&atom.name)?;
+            for name in atom.args.iter().filter_map(Term::as_var) {
+                check_variable(name)?;
+            }
+            Ok(())
+        }
+        QueryProjection::Comparison(comparison) => {
+            for term in [&comparison.left, &comparison.right] {
+                if let Term::Var(name) = term {
+                    check_variable(name)?;
+                }
+            }
+            Ok(())
+        }
+        QueryProjection::Negation(item) => check_item(item),
+    }
+}
+
+// Atoms skip `_` through `Term::as_var`, while comparisons reject it like the parser
+fn check_variable(name: &str) -> Result<(), String> {
+    if is_whole(name, parse_variable) {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not a variable name", name))
+    }
+}
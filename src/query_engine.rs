@@ -1,178 +1,500 @@
+use crate::api::Answers;
 use crate::api::Database;
-use crate::api::DatabaseInstance;
-use crate::parser::parse_datalog;
+use crate::api::QueryResult;
+use crate::convert::{ConversionError, FromRow};
+use crate::diagnostics::{parse_with_diagnostics, Diagnostic, Span};
+use crate::parser::Atom;
 use crate::parser::DatalogItem;
 use crate::parser::NonQueryDatalogItem;
+use crate::parser::Query;
 use crate::parser::QueryProjection;
+use crate::parser::Rule;
+use crate::parser::Term;
 use crate::parser::VariableBasedRelation;
+use crate::value::Value;
 use itertools::Itertools;
+use std::fmt;
+use std::io;
 use std::io::Write;
-use std::sync::Mutex;
-use std::sync::OnceLock;
 
-static DB_INSTANCE: OnceLock<Mutex<DatabaseInstance>> = OnceLock::new();
+// File name used in errors about sources that were not read from a file
+const INPUT: &str = "<input>";
 
-fn get_db_instance() -> &'static Mutex<DatabaseInstance> {
-    DB_INSTANCE.get_or_init(|| Mutex::new(DatabaseInstance::new()))
+/// Why a program or a query could not be run
+#[derive(Debug)]
+pub enum Error {
+    /// The source has syntax errors, all of them are listed. Nothing in it was run.
+    Syntax(Vec<Diagnostic>),
+    /// A statement was rejected, such as a fact with the wrong number of
+    /// arguments or a query given to `Engine::load`.
+    /// The statements before it were run.
+    Statement(Diagnostic),
+    /// Writing the output failed
+    Io(io::Error),
+    /// Answers could not be converted by `query_as`
+    Conversion(ConversionError),
 }
 
-pub fn execute_query<W: Write>(query: NonQueryDatalogItem, db: &Database, writer: &mut W) {
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Syntax(diagnostics) => write!(f, "{}", diagnostics.iter().format("\n")),
+            Error::Statement(diagnostic) => write!(f, "{}", diagnostic),
+            Error::Io(e) => write!(f, "failed to write output: {}", e),
+            Error::Conversion(e) => write!(f, "failed to convert answers: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Conversion(e) => Some(e),
+            Error::Syntax(_) | Error::Statement(_) => None,
+        }
+    }
+}
+
+impl From<ConversionError> for Error {
+    fn from(e: ConversionError) -> Self {
+        Error::Conversion(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+fn format_query_projection(item: &QueryProjection) -> String {
+    match item {
+        QueryProjection::Atom(atom) => atom.to_string(),
+        QueryProjection::Comparison(c) => c.to_string(),
+        QueryProjection::Negation(q) => format!("not {}", format_query_projection(q)),
+    }
+}
+
+fn format_body_item(item: &DatalogItem) -> String {
+    match item {
+        DatalogItem::Atom(atom) => atom.to_string(),
+        DatalogItem::Comparison(comparison) => comparison.to_string(),
+        DatalogItem::Negation(item) => format!("not {}", format_body_item(item)),
+        _ => String::new(), // Ignore for now
+    }
+}
+
+// Constants are shown without quotes
+fn describe_term(term: &Term) -> String {
+    match term {
+        Term::Const(value) => value.to_string(),
+        Term::Var(_) | Term::Param(_) => term.to_string(),
+    }
+}
+
+// "male is Bob", "parent is Alice of Bob", "born(Alice, 1970, Paris)"
+fn describe_atom(atom: &Atom) -> String {
+    match atom.args.as_slice() {
+        [] => atom.name.clone(),
+        [first] => format!("{} is {}", atom.name, describe_term(first)),
+        [first, second] => format!(
+            "{} is {} of {}",
+            atom.name,
+            describe_term(first),
+            describe_term(second)
+        ),
+        args => format!(
+            "{}({})",
+            atom.name,
+            args.iter().map(describe_term).format(", ")
+        ),
+    }
+}
+
+// A header with the variable names, then one row per answer, columns aligned:
+//
+// P   | C
+// Bob | Charlie
+//
+// Queries without variables print true or false
+fn write_answers<W: Write>(writer: &mut W, answers: &Answers) -> io::Result<()> {
+    if answers.variables.is_empty() {
+        return writeln!(writer, "{}", !answers.is_empty());
+    }
+    let rows: Vec<Vec<String>> = answers
+        .rows
+        .iter()
+        .map(|row| row.iter().map(Value::to_string).collect())
+        .collect();
+    let widths: Vec<usize> = answers
+        .variables
+        .iter()
+        .enumerate()
+        .map(|(i, variable)| {
+            rows.iter()
+                .map(|row| row[i].chars().count())
+                .chain([variable.chars().count()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |cells: &[String]| {
+        let last = cells.len() - 1;
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                if i == last {
+                    cell.clone()
+                } else {
+                    format!("{:width$}", cell, width = widths[i])
+                }
+            })
+            .join(" | ")
+    };
+    writeln!(writer, "{}", line(&answers.variables))?;
+    for row in &rows {
+        writeln!(writer, "{}", line(row))?;
+    }
+    Ok(())
+}
+
+// "Alice, Bob", the values of the only column
+fn write_values<W: Write>(writer: &mut W, answers: &Answers) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{}",
+        answers.rows.iter().map(|row| &row[0]).format(", ")
+    )
+}
+
+/// Writes the header printed before the answers of a query
+// "Query: list all where parent(X, _)", "Query: Who is parent of Bob?"
+fn write_query<W: Write>(writer: &mut W, query: &NonQueryDatalogItem) -> io::Result<()> {
     match query {
         NonQueryDatalogItem::QueryProjectionFact(query) => {
-            writeln!(writer, "Query: list all where {}(_)", query.name).unwrap();
-            let results = db.query_projection_fact(query);
-            writeln!(writer, "{}", results.iter().map(|r| r).format(", ")).unwrap();
-        }
-        NonQueryDatalogItem::QueryProjectionRelation(query) => {
-            writeln!(
-                writer,
-                "Query: list all where {}({}, {})",
-                query.name, query.first, query.second
-            )
-            .unwrap();
-            let results = db.query_projection_relation(query);
-            writeln!(writer, "{}", results.iter().map(|r| r).format(", ")).unwrap();
+            writeln!(writer, "Query: list all where {}(_)", query.name)
         }
+        NonQueryDatalogItem::QueryProjectionRelation(query) => writeln!(
+            writer,
+            "Query: list all where {}({}, {})",
+            query.name, query.first, query.second
+        ),
         NonQueryDatalogItem::ConjunctiveQuery(query) => {
-            // writeln!(writer, "Query: {}", query.data).unwrap();
             let mut text = String::new();
             for el in &query.data {
-                match el {
-                    QueryProjection::QueryProjectionFact(q) => {
-                        // hardcode X as only possibility
-                        // if we change the parser to allow more variables,
-                        // we will need to change
-                        text.push_str(&format!("\n    {}(X)", q.name));
-                    }
-                    QueryProjection::QueryProjectionRelation(q) => {
-                        text.push_str(&format!("\n    {}({}, {})", q.name, q.first, q.second));
-                    }
-                }
+                text.push_str(&format!("\n    {}", format_query_projection(el)));
             }
-            writeln!(writer, "Query: list all where:{}", text).unwrap();
-            let results = db.query_conjunctive(query);
-            writeln!(writer, "{}", results.iter().map(|r| r).format(", ")).unwrap();
+            writeln!(writer, "Query: list all where:{}", text)
+        }
+        NonQueryDatalogItem::Atom(atom) => {
+            // Binary atoms read as a question, as in "parent is Alice of Bob?"
+            let question = if atom.arity() == 1 { "" } else { "?" };
+            writeln!(writer, "Query: {}{}", describe_atom(atom), question)
+        }
+        NonQueryDatalogItem::VariableBasedRelation(
+            VariableBasedRelation::VariableBasedRelationFirstIsVar(rel),
+        ) => writeln!(writer, "Query: Who is {} of {}?", rel.name, rel.second),
+        NonQueryDatalogItem::VariableBasedRelation(
+            VariableBasedRelation::VariableBasedRelationSecondIsVar(rel),
+        ) => writeln!(writer, "Query: Of whom is {} {}?", rel.first, rel.name),
+        NonQueryDatalogItem::Rule(_) => Ok(()),
+    }
+}
+
+// "father of X, Y means parent(X, Y), male(X)"
+fn describe_rule(rule: &Rule) -> String {
+    let head = if rule.head.args.is_empty() {
+        rule.head.name.clone()
+    } else {
+        format!(
+            "{} of {}",
+            rule.head.name,
+            rule.head.args.iter().format(", ")
+        )
+    };
+    format!(
+        "{} means {}",
+        head,
+        rule.definition
+            .relations
+            .iter()
+            .map(format_body_item)
+            .format(", ")
+    )
+}
+
+// Parses a whole source, failing with all of its syntax errors
+fn parse(source: &str, file: &str) -> Result<Vec<DatalogItem>, Error> {
+    let (items, diagnostics) = parse_with_diagnostics(source, file);
+    if diagnostics.is_empty() {
+        Ok(items)
+    } else {
+        Err(Error::Syntax(diagnostics))
+    }
+}
+
+/// Parses a source holding exactly one query
+pub(crate) fn parse_query_source(source: &str) -> Result<Query, Error> {
+    let mut items = parse(source, INPUT)?.into_iter();
+    match (items.next(), items.next()) {
+        (Some(DatalogItem::Query(query)), None) => Ok(query),
+        (first, second) => {
+            let span = match second.or(first) {
+                Some(item) => item.span(),
+                None => Span::default(),
+            };
+            let message = "expected a single query, such as `?parent(X, \"Bob\").`";
+            Err(Error::Statement(Diagnostic::at(
+                INPUT, source, span, message,
+            )))
         }
-        NonQueryDatalogItem::Relation(rel) => {
-            writeln!(
-                writer,
-                "Query: {} is {} of {}?",
-                rel.name, rel.first, rel.second
-            )
-            .unwrap();
-            if db.contains_relation(&rel) {
-                writeln!(writer, "true").unwrap();
-            } else {
-                writeln!(writer, "false").unwrap();
+    }
+}
+
+/// An error about the query at `span` of `source`
+pub(crate) fn query_error(source: &str, span: Span, message: String) -> Error {
+    Error::Statement(Diagnostic::at(INPUT, source, span, message))
+}
+
+/// A database and the programs and queries run against it.
+/// Every engine owns its database, so several can be used side by side.
+#[derive(Default)]
+pub struct Engine {
+    db: Database,
+}
+
+impl Engine {
+    /// Creates an engine with an empty database
+    pub fn new() -> Self {
+        Engine {
+            db: Database::new(),
+        }
+    }
+
+    /// Creates an engine working on `db`, such as one read from a snapshot
+    pub fn with_database(db: Database) -> Self {
+        Engine { db }
+    }
+
+    /// Gets a reference to the underlying database
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+
+    /// Gets a mutable reference to the underlying database
+    pub fn database_mut(&mut self) -> &mut Database {
+        &mut self.db
+    }
+
+    /// Takes the database out of the engine
+    pub fn into_database(self) -> Database {
+        self.db
+    }
+
+    /// Adds the facts and rules of `source` to the database.
+    /// Nothing is added if it has syntax errors. A statement that is rejected,
+    /// such as a query, stops loading.
+    pub fn load(&mut self, source: &str) -> Result<(), Error> {
+        for item in parse(source, INPUT)? {
+            match item {
+                DatalogItem::Query(query) => {
+                    let message = "queries can not be loaded, use `run` or `query`";
+                    return Err(Error::Statement(Diagnostic::at(
+                        INPUT, source, query.span, message,
+                    )));
+                }
+                item => self.execute(item, source, INPUT, &mut io::sink())?,
             }
         }
-        NonQueryDatalogItem::Fact(fact) => {
-            writeln!(writer, "Query: {} is {}", fact.name, fact.first).unwrap();
-            if db.contains_fact(&fact) {
-                writeln!(writer, "true").unwrap();
-            } else {
-                writeln!(writer, "false").unwrap();
+        Ok(())
+    }
+
+    /// Runs the statements of `source` in order, writing what each of them
+    /// means and the answers to its queries to `writer`.
+    /// Nothing is run if it has syntax errors, and the first rejected
+    /// statement stops the program.
+    pub fn run<W: Write>(&mut self, source: &str, writer: &mut W) -> Result<(), Error> {
+        self.run_file(source, INPUT, writer)
+    }
+
+    /// Like `run`, naming `file` in errors
+    pub fn run_file<W: Write>(
+        &mut self,
+        source: &str,
+        file: &str,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        for item in parse(source, file)? {
+            self.execute(item, source, file, writer)?;
+        }
+        Ok(())
+    }
+
+    /// Answers a single query such as `?parent(X, "Bob").`, deriving the
+    /// relations of the rules first. See `Database::query`.
+    pub fn query(&mut self, query: &str) -> Result<QueryResult, Error> {
+        self.db.query(query)
+    }
+
+    /// Like `query`, converting every answer. See `Database::query_as`.
+    pub fn query_as<T: FromRow>(&mut self, query: &str) -> Result<Vec<T>, Error> {
+        self.db.query_as(query)
+    }
+
+    /// Runs a program the way the command line does: statements with syntax
+    /// errors are skipped, and a rejected statement does not stop the ones
+    /// after it. Returns everything that went wrong.
+    pub fn interpret<W: Write>(
+        &mut self,
+        source: &str,
+        file: &str,
+        writer: &mut W,
+    ) -> io::Result<Vec<Diagnostic>> {
+        let (items, mut diagnostics) = parse_with_diagnostics(source, file);
+        if items.is_empty() && diagnostics.is_empty() {
+            writeln!(writer, "No valid datalog items found")?;
+        }
+        for item in items {
+            match self.execute(item, source, file, writer) {
+                Ok(()) => {}
+                Err(Error::Syntax(more)) => diagnostics.extend(more),
+                Err(Error::Statement(diagnostic)) => diagnostics.push(diagnostic),
+                Err(Error::Io(e)) => return Err(e),
+                Err(Error::Conversion(_)) => unreachable!("statements convert no answers"),
             }
         }
-        // rules will create relations, so we can query those too
-        NonQueryDatalogItem::VariableBasedRelation(item) => match item {
-            VariableBasedRelation::VariableBasedRelationFirstIsVar(rel) => {
-                writeln!(writer, "Query: Who is {} of {}?", rel.name, rel.second).unwrap();
-                let relations = db.relations_where_second_is(&rel.name, &rel.second);
-                writeln!(
-                    writer,
-                    "{}",
-                    relations.iter().map(|r| &r.first).format(", ")
-                )
-                .unwrap();
+        Ok(diagnostics)
+    }
+
+    fn execute<W: Write>(
+        &mut self,
+        item: DatalogItem,
+        source: &str,
+        file: &str,
+        writer: &mut W,
+    ) -> Result<(), Error> {
+        let span = item.span();
+        let rejected = |e: String| Error::Statement(Diagnostic::at(file, source, span, e));
+        match item {
+            DatalogItem::Atom(atom) => {
+                writeln!(writer, "{}", describe_atom(&atom))?;
+                self.db.add_atom(atom).map_err(rejected)
             }
-            VariableBasedRelation::VariableBasedRelationSecondIsVar(rel) => {
-                writeln!(writer, "Query: Of whom is {} {}?", rel.first, rel.name).unwrap();
-                let relations = db.relations_where_first_is(&rel.name, &rel.first);
-                writeln!(
-                    writer,
-                    "{}",
-                    relations.iter().map(|r| &r.second).format(", ")
-                )
-                .unwrap();
+            DatalogItem::Rule(rule) => {
+                writeln!(writer, "{}", describe_rule(&rule))?;
+                // The atoms it derives are materialized before the next query
+                self.db.add_rule(rule).map_err(rejected)
             }
-        },
-        _ => eprintln!("Unsupported query type"),
-    }
-}
-
-// @param reset_db: If true, clears the database before interpreting the input
-pub fn interpret<W: Write>(input: &str, writer: &mut W, reset_db: Option<bool>) {
-    let reset_db = reset_db.unwrap_or(false);
-    let mut db_guard = get_db_instance().lock().unwrap();
-    let db = db_guard.get_db_mut();
-    if reset_db {
-        db.clear();
-        // Reinitialize the database instance by replacing it with a new one
-        let _ = DB_INSTANCE.set(Mutex::new(DatabaseInstance::new()));
-    }
-
-    match parse_datalog(input) {
-        Ok((_, items)) => {
-            if items.is_empty() {
-                writeln!(writer, "No valid datalog items found").unwrap();
-            } else {
-                for item in items {
-                    match item {
-                        DatalogItem::Fact(fact) => {
-                            writeln!(writer, "{} is {}", fact.name, fact.first).unwrap();
-                            db.add_fact(fact);
-                        }
-                        DatalogItem::Relation(relation) => {
-                            writeln!(
-                                writer,
-                                "{} is {} of {}",
-                                relation.name, relation.first, relation.second
-                            )
-                            .unwrap();
-                            db.add_relation(relation);
-                        }
-                        DatalogItem::Rule(rule) => {
-                            writeln!(
-                                writer,
-                                "{} of {}, {} means {}",
-                                rule.name,
-                                rule.first,
-                                rule.second,
-                                rule.definition
-                                    .relations
-                                    .iter()
-                                    .map(|r| {
-                                        match r {
-                                            DatalogItem::Relation(rel) => {
-                                                format!(
-                                                    "{}({}, {})",
-                                                    rel.name, rel.first, rel.second
-                                                )
-                                            }
-                                            DatalogItem::Fact(fact) => {
-                                                format!("{}({})", fact.name, fact.first)
-                                            }
-                                            _ => String::new(), // Ignore for now
-                                        }
-                                    })
-                                    .format(", ")
-                            )
-                            .unwrap();
-                            //interpreting a rule should create relations so later we can query them with query relations
-                            // only if the rule definition matches
-                            // step 1. check every part of the rule
-                            // step 2. If all match create the relation
-                            // TODO:
-                        }
-                        DatalogItem::Query(query) => {
-                            // Use the already locked database instance
-                            execute_query(query.data, db, writer);
-                        }
-                    }
+            // Comparisons and negations only appear inside rule bodies and queries
+            DatalogItem::Comparison(_) | DatalogItem::Negation(_) => Ok(()),
+            DatalogItem::Query(query) => {
+                self.db.evaluate_rules();
+                // A rejected query prints nothing, not even its header
+                let answers = self.db.answer(query.data.clone()).map_err(rejected)?;
+                write_query(writer, &query.data)?;
+                // Questions about a single unknown are answered by a list
+                let listed = matches!(
+                    query.data,
+                    NonQueryDatalogItem::QueryProjectionFact(_)
+                        | NonQueryDatalogItem::VariableBasedRelation(_)
+                );
+                if listed {
+                    write_values(writer, &answers)?;
+                } else {
+                    write_answers(writer, &answers)?;
                 }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Runs a program with a fresh engine, writing its output to `writer`.
+/// Returns everything that went wrong, as `Engine::interpret`.
+pub fn interpret<W: Write>(input: &str, writer: &mut W) -> io::Result<Vec<Diagnostic>> {
+    interpret_file(input, INPUT, writer)
+}
+
+/// Like `interpret`, naming `file` in error messages.
+/// Every syntax error is reported, and the statements that do parse still run.
+/// Errors found while running a statement point at that statement.
+pub fn interpret_file<W: Write>(
+    input: &str,
+    file: &str,
+    writer: &mut W,
+) -> io::Result<Vec<Diagnostic>> {
+    Engine::new().interpret(input, file, writer)
+}
+
+/// Builds a database from a program written in Rust source, for tests and
+/// small tools:
+///
+/// ```
+/// use dataloglite::datalog;
+///
+/// let mut db = datalog! {
+///     parent("Alice", "Bob").
+///     parent("Bob", "Carl").
+///     ancestor(X, Y) :- parent(X, Y).
+///     ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+/// };
+/// assert!(db.query(r#"?ancestor("Alice", "Carl")."#).unwrap().holds());
+/// ```
+///
+/// The program is read by the same parser as any other, when the macro runs.
+/// If it is invalid the macro panics with every error, located in the Rust
+/// file. Written as tokens, errors only name the line of the macro, as the
+/// lines of the tokens are lost; written as a string literal starting on the
+/// line of the macro, as `datalog! {r#"...`, they point at the exact line and
+/// column.
+#[macro_export]
+macro_rules! datalog {
+    ($source:literal) => {
+        $crate::query_engine::load_embedded($source, file!(), line!(), true)
+    };
+    ($($program:tt)*) => {
+        $crate::query_engine::load_embedded(stringify!($($program)*), file!(), line!(), false)
+    };
+}
+
+/// Loads the program of `datalog!`, which starts at `line` of `file`.
+/// `exact` if the lines of `source` are those of the file, which holds for a
+/// string literal starting on the line of the macro.
+#[doc(hidden)]
+pub fn load_embedded(source: &str, file: &str, line: u32, exact: bool) -> Database {
+    // Written as tokens, the column and the snippet would be those of the
+    // program as Rust printed it, not of the file
+    let relocate = |diagnostic: Diagnostic| {
+        if exact {
+            let line = line as usize + diagnostic.line - 1;
+            Diagnostic {
+                file: file.to_string(),
+                line,
+                ..diagnostic
             }
+            .to_string()
+        } else {
+            format!("error: {}\n --> {}:{}", diagnostic.message, file, line)
+        }
+    };
+    // Rust wraps long programs, in the middle of statements
+    let source = if exact {
+        source.to_string()
+    } else {
+        source.replace('\n', " ")
+    };
+    let mut engine = Engine::new();
+    match engine.load(&source) {
+        Ok(()) => engine.into_database(),
+        Err(Error::Syntax(diagnostics)) => panic!(
+            "invalid datalog! program\n{}",
+            diagnostics.into_iter().map(relocate).format("\n")
+        ),
+        Err(Error::Statement(diagnostic)) => {
+            panic!("invalid datalog! program\n{}", relocate(diagnostic))
         }
-        Err(e) => eprintln!("Error parsing datalog: {}", e),
+        Err(e) => panic!("invalid datalog! program\n{}", e),
     }
 }
@@ -0,0 +1,237 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::value::Value;
+
+// A snapshot is:
+//   "DLLSNAP\0"  magic bytes
+//   u32          format version
+//   ...          the database, see `Database::to_snapshot`
+//   u32          CRC-32 of everything before it
+// Numbers are little endian, strings are a u64 length then UTF-8 bytes.
+const MAGIC: &[u8; 8] = b"DLLSNAP\0";
+
+/// Version of the snapshot format written by this build.
+/// Snapshots of other versions are rejected rather than misread.
+pub const VERSION: u32 = 1;
+
+const HEADER: usize = MAGIC.len() + 4;
+const CHECKSUM: usize = 4;
+
+/// Writes the parts of a snapshot in order
+pub(crate) struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    pub(crate) fn new() -> Self {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        Encoder { bytes }
+    }
+
+    pub(crate) fn u8(&mut self, n: u8) {
+        self.bytes.push(n);
+    }
+
+    pub(crate) fn u32(&mut self, n: u32) {
+        self.bytes.extend_from_slice(&n.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, n: u64) {
+        self.bytes.extend_from_slice(&n.to_le_bytes());
+    }
+
+    pub(crate) fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    pub(crate) fn str(&mut self, s: &str) {
+        self.len(s.len());
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    pub(crate) fn value(&mut self, value: &Value) {
+        match value {
+            Value::Integer(i) => {
+                self.u8(0);
+                self.u64(*i as u64);
+            }
+            Value::Float(x) => {
+                self.u8(1);
+                self.u64(x.to_bits());
+            }
+            Value::String(s) => {
+                self.u8(2);
+                self.str(s);
+            }
+        }
+    }
+
+    /// An encoder without the snapshot header, for parts written on their own
+    pub(crate) fn raw() -> Self {
+        Encoder { bytes: Vec::new() }
+    }
+
+    /// The bytes written, without a checksum
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// The snapshot, with its checksum
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        let checksum = crc32(&self.bytes);
+        self.u32(checksum);
+        self.bytes
+    }
+}
+
+/// Reads the parts of a snapshot in the order they were written
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    /// Checks the header and the checksum of `bytes`
+    pub(crate) fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER + CHECKSUM || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid("not a dataloglite snapshot"));
+        }
+        let version = u32::from_le_bytes(bytes[MAGIC.len()..HEADER].try_into().unwrap());
+        if version != VERSION {
+            return Err(invalid(format!(
+                "snapshot format version {} is not supported, expected {}",
+                version, VERSION
+            )));
+        }
+        let (content, checksum) = bytes.split_at(bytes.len() - CHECKSUM);
+        if crc32(content) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err(invalid("snapshot checksum mismatch, the file is damaged"));
+        }
+        Ok(Decoder {
+            bytes: content,
+            position: HEADER,
+        })
+    }
+
+    /// A decoder of bytes without the snapshot header and checksum
+    pub(crate) fn raw(bytes: &'a [u8]) -> Self {
+        Decoder { bytes, position: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| invalid("snapshot ends unexpectedly"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn len(&mut self) -> io::Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid("snapshot length out of range"))
+    }
+
+    pub(crate) fn str(&mut self) -> io::Result<&'a str> {
+        let len = self.len()?;
+        std::str::from_utf8(self.take(len)?).map_err(|_| invalid("snapshot string is not UTF-8"))
+    }
+
+    pub(crate) fn value(&mut self) -> io::Result<Value> {
+        match self.u8()? {
+            0 => Ok(Value::Integer(self.u64()? as i64)),
+            1 => Ok(Value::Float(f64::from_bits(self.u64()?))),
+            2 => Ok(Value::from(self.str()?)),
+            tag => Err(invalid(format!("unknown value type {} in snapshot", tag))),
+        }
+    }
+
+    /// Fails if anything is left after the last part
+    pub(crate) fn finish(self) -> io::Result<()> {
+        if self.position == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(invalid("unexpected data at the end of the snapshot"))
+        }
+    }
+}
+
+/// Writes `bytes` to `path` through a temporary file next to it, renamed
+/// over `path` once synced, so a crash leaves either the old file or the new
+/// one and never a partial snapshot. The directory is synced too, so the
+/// rename itself survives a crash.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)?;
+    sync_directory(path)
+}
+
+// Directories cannot be opened as files on Windows, where renames are
+// written through instead
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    File::open(directory)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// An error about the content of a snapshot
+pub(crate) fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// CRC-32 (IEEE), as used by zip and PNG
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut crc = n as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[n] = crc;
+        n += 1;
+    }
+    table
+};
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
@@ -0,0 +1,780 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+use std::io;
+use std::sync::OnceLock;
+
+use crate::parser::{Atom, Term};
+use crate::snapshot::{self, Decoder, Encoder};
+use crate::value::Value;
+
+/// The id of a value interned in a `Symbols` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Every distinct value stored in a database, kept once. Tuples hold the
+/// symbols of their values, and values are looked up again only to answer
+/// queries.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    // The value of each symbol, by its id
+    values: Vec<Value>,
+    // Finds the symbol of a value without a second copy of it
+    ids: Slots,
+    // Randomly keyed, so that values chosen to collide cannot make probing slow
+    hasher: RandomState,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    /// The symbol of `value`, adding it to the table if it is new
+    pub fn intern(&mut self, value: &Value) -> Symbol {
+        if let Some(symbol) = self.get(value) {
+            return symbol;
+        }
+        let symbol = Symbol(u32::try_from(self.values.len()).expect("too many distinct values"));
+        let (values, hasher) = (&self.values, &self.hasher);
+        self.ids.insert(hasher.hash_one(value), values.len(), |id| {
+            hasher.hash_one(&values[id])
+        });
+        self.values.push(value.clone());
+        symbol
+    }
+
+    /// The symbol of `value`, `None` if nothing stored has that value
+    pub fn get(&self, value: &Value) -> Option<Symbol> {
+        let slot = self
+            .ids
+            .find(self.hasher.hash_one(value), value, |id| &self.values[id])?;
+        Some(Symbol(self.ids.position(slot) as u32))
+    }
+
+    /// The value of a symbol of this table
+    pub fn resolve(&self, symbol: Symbol) -> &Value {
+        &self.values[symbol.0 as usize]
+    }
+
+    /// Number of distinct values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The symbols and their values, from the first interned
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, &Value)> {
+        (0u32..).map(Symbol).zip(&self.values)
+    }
+
+    /// The symbols of the arguments of a ground atom, interning new values
+    pub fn intern_atom(&mut self, atom: &Atom) -> Vec<Symbol> {
+        atom.args
+            .iter()
+            .map(|arg| self.intern(arg.as_value().expect("stored atoms are ground")))
+            .collect()
+    }
+
+    /// The symbols of the arguments of `atom`, `None` if one of them is not
+    /// a stored value
+    pub fn get_atom(&self, atom: &Atom) -> Option<Vec<Symbol>> {
+        atom.args
+            .iter()
+            .map(|arg| self.get(arg.as_value()?))
+            .collect()
+    }
+
+    /// The atom of predicate `name` with the values of `tuple`
+    pub fn resolve_atom(&self, name: &str, tuple: &[Symbol]) -> Atom {
+        Atom::new(
+            name,
+            tuple
+                .iter()
+                .map(|&symbol| Term::Const(self.resolve(symbol).clone()))
+                .collect(),
+        )
+    }
+}
+
+impl Symbols {
+    pub(crate) fn encode(&self, encoder: &mut Encoder) {
+        encoder.len(self.values.len());
+        for value in &self.values {
+            encoder.value(value);
+        }
+    }
+
+    /// Reads back a table, keeping the symbols it was saved with
+    pub(crate) fn decode(decoder: &mut Decoder) -> io::Result<Self> {
+        let mut symbols = Symbols::new();
+        for _ in 0..decoder.len()? {
+            let value = decoder.value()?;
+            if symbols.get(&value).is_some() {
+                return Err(snapshot::invalid(format!(
+                    "value {} is twice in the snapshot",
+                    value.to_source()
+                )));
+            }
+            symbols.intern(&value);
+        }
+        Ok(symbols)
+    }
+}
+
+/// A hash table of positions into a vector kept by its owner, which gives
+/// the key and the hash of the entry at each position. Used to find entries
+/// without keeping a copy of their keys. Collisions are resolved by linear
+/// probing.
+#[derive(Debug, Clone, Default)]
+struct Slots {
+    // A position plus one, or 0 for an empty slot. The length is 0 or a power
+    // of two.
+    slots: Vec<u32>,
+    len: usize,
+}
+
+impl Slots {
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    fn position(&self, slot: usize) -> usize {
+        self.slots[slot] as usize - 1
+    }
+
+    /// The slot holding an entry equal to `key`, which hashes to `hash`
+    fn find<'a, K: PartialEq + ?Sized + 'a>(
+        &self,
+        hash: u64,
+        key: &K,
+        key_at: impl Fn(usize) -> &'a K,
+    ) -> Option<usize> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let mut slot = hash as usize & self.mask();
+        while self.slots[slot] != 0 {
+            if key_at(self.position(slot)) == key {
+                return Some(slot);
+            }
+            slot = (slot + 1) & self.mask();
+        }
+        None
+    }
+
+    /// Adds the entry at `position`, which hashes to `hash`. It must not be
+    /// in the table already.
+    fn insert(&mut self, hash: u64, position: usize, hash_at: impl Fn(usize) -> u64) {
+        self.reserve(hash_at);
+        let entry = u32::try_from(position + 1).expect("too many entries");
+        self.place(hash, entry);
+        self.len += 1;
+    }
+
+    // Puts `entry` in the first empty slot from the one of `hash`
+    fn place(&mut self, hash: u64, entry: u32) {
+        let mut slot = hash as usize & self.mask();
+        while self.slots[slot] != 0 {
+            slot = (slot + 1) & self.mask();
+        }
+        self.slots[slot] = entry;
+    }
+
+    // Makes room for one more entry, keeping the table at most 3/4 full
+    fn reserve(&mut self, hash_at: impl Fn(usize) -> u64) {
+        if (self.len + 1) * 4 <= self.slots.len() * 3 {
+            return;
+        }
+        let capacity = (self.slots.len() * 2).max(8);
+        let old = std::mem::replace(&mut self.slots, vec![0; capacity]);
+        for entry in old.into_iter().filter(|&entry| entry != 0) {
+            self.place(hash_at(entry as usize - 1), entry);
+        }
+    }
+
+    /// Points `slot` at the entry now at `position`
+    fn set(&mut self, slot: usize, position: usize) {
+        self.slots[slot] = u32::try_from(position + 1).expect("too many entries");
+    }
+
+    /// Empties `slot`, moving back the entries after it that were placed past
+    /// their own slot, so that probing still finds them
+    fn remove(&mut self, slot: usize, hash_at: impl Fn(usize) -> u64) {
+        let mut empty = slot;
+        let mut next = (slot + 1) & self.mask();
+        while self.slots[next] != 0 {
+            let home = hash_at(self.position(next)) as usize & self.mask();
+            // Whether `home` lies cyclically in (empty, next]: then the entry
+            // is already reachable without going through `empty`
+            let stays = if empty <= next {
+                empty < home && home <= next
+            } else {
+                empty < home || home <= next
+            };
+            if !stays {
+                self.slots[empty] = self.slots[next];
+                empty = next;
+            }
+            next = (next + 1) & self.mask();
+        }
+        self.slots[empty] = 0;
+        self.len -= 1;
+    }
+}
+
+/// Positions of the tuples having each symbol in a column
+type Index = HashMap<Symbol, Vec<usize>>;
+
+/// The tuples of one predicate with a given number of arguments.
+/// A column is indexed by the first lookup on it, then kept up to date, so
+/// only the columns that queries and rules bind are indexed.
+#[derive(Debug, Clone)]
+pub struct Relation {
+    arity: usize,
+    len: usize,
+    // The symbols of every tuple, one tuple after the other, each kept once
+    symbols: Vec<Symbol>,
+    // Finds the position of a tuple, hashed as in `Symbols`
+    positions: Slots,
+    hasher: RandomState,
+    indexes: Vec<OnceLock<Index>>,
+}
+
+impl Relation {
+    fn new(arity: usize) -> Self {
+        Relation {
+            arity,
+            len: 0,
+            symbols: Vec::new(),
+            positions: Slots::default(),
+            hasher: RandomState::new(),
+            indexes: (0..arity).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    fn find(&self, tuple: &[Symbol]) -> Option<usize> {
+        self.positions
+            .find(self.hasher.hash_one(tuple), tuple, |position| {
+                self.tuple(position)
+            })
+    }
+
+    // Adds the tuple at `position`, which hashes to `hash_value`, to `positions`
+    fn index_position(&mut self, hash_value: u64, position: usize) {
+        let (symbols, arity, hasher) = (&self.symbols, self.arity, &self.hasher);
+        self.positions.insert(hash_value, position, |position| {
+            hasher.hash_one(&symbols[position * arity..(position + 1) * arity])
+        });
+    }
+
+    /// Adds `tuple`, returning whether it was new
+    fn insert(&mut self, tuple: &[Symbol]) -> bool {
+        if self.find(tuple).is_some() {
+            return false;
+        }
+        for (index, &symbol) in self.indexes.iter_mut().zip(tuple) {
+            if let Some(index) = index.get_mut() {
+                index.entry(symbol).or_default().push(self.len);
+            }
+        }
+        self.index_position(self.hasher.hash_one(tuple), self.len);
+        self.symbols.extend_from_slice(tuple);
+        self.len += 1;
+        true
+    }
+
+    /// Removes `tuple`, returning whether it was there.
+    /// The last tuple takes its place, and the positions and the indexes are
+    /// patched for the two of them.
+    fn remove(&mut self, tuple: &[Symbol]) -> bool {
+        let Some(slot) = self.find(tuple) else {
+            return false;
+        };
+        let position = self.positions.position(slot);
+        let last = self.len - 1;
+        let (symbols, arity, hasher) = (&self.symbols, self.arity, &self.hasher);
+        self.positions.remove(slot, |position| {
+            hasher.hash_one(&symbols[position * arity..(position + 1) * arity])
+        });
+        for (column, index) in self.indexes.iter_mut().enumerate() {
+            let Some(index) = index.get_mut() else {
+                continue;
+            };
+            let symbol = tuple[column];
+            let positions = index.get_mut(&symbol).expect("indexed tuples are stored");
+            positions.retain(|&other| other != position);
+            if positions.is_empty() {
+                index.remove(&symbol);
+            }
+            if position != last {
+                let moved = self.symbols[last * arity + column];
+                for other in index.get_mut(&moved).expect("indexed tuples are stored") {
+                    if *other == last {
+                        *other = position;
+                    }
+                }
+            }
+        }
+        if position != last {
+            let moved = self
+                .find(self.tuple(last))
+                .expect("stored tuples are found");
+            self.positions.set(moved, position);
+            let (removed, moved) = self.symbols.split_at_mut(last * arity);
+            removed[position * arity..(position + 1) * arity].copy_from_slice(moved);
+        }
+        self.symbols.truncate(last * arity);
+        self.len = last;
+        true
+    }
+
+    pub fn contains(&self, tuple: &[Symbol]) -> bool {
+        self.find(tuple).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn tuple(&self, position: usize) -> &[Symbol] {
+        &self.symbols[position * self.arity..(position + 1) * self.arity]
+    }
+
+    /// The tuples, in the order they were added, except that removing a tuple
+    /// moves the last one into its place
+    pub fn tuples(&self) -> impl Iterator<Item = &[Symbol]> {
+        (0..self.len).map(|position| self.tuple(position))
+    }
+
+    /// The tuples having `symbol` as argument `column`, found through the
+    /// index on that column
+    pub fn lookup(&self, column: usize, symbol: Symbol) -> impl Iterator<Item = &[Symbol]> {
+        let index = self.indexes[column].get_or_init(|| {
+            let mut index = Index::new();
+            for position in 0..self.len {
+                index
+                    .entry(self.tuple(position)[column])
+                    .or_default()
+                    .push(position);
+            }
+            index
+        });
+        index
+            .get(&symbol)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|&position| self.tuple(position))
+    }
+
+    /// Whether `column` has been indexed
+    pub fn is_indexed(&self, column: usize) -> bool {
+        self.indexes
+            .get(column)
+            .is_some_and(|index| index.get().is_some())
+    }
+}
+
+/// Tuples read from a `Storage`
+pub type Tuples<'a> = Box<dyn Iterator<Item = &'a [Symbol]> + 'a>;
+
+/// Where a database keeps atoms, as tuples of symbols grouped by predicate.
+/// `AtomSet` keeps them in memory and is used by `Database::new`. Other
+/// backends, such as a sorted store, implement this trait and are given to
+/// `Database::with_storage` or `Database::load_snapshot_with`; the evaluator
+/// and the queries only go through it.
+///
+/// Backends are `Send + Sync`, so that a database can be moved to another
+/// thread or shared behind a lock.
+///
+/// Tuples hold symbols, whose values are in the `Symbols` table of the
+/// database. A backend that keeps its tuples across processes, such as an
+/// on-disk store, keeps their values too through `add_value` and gives them
+/// back through `values`, so that a database made on it again knows them.
+pub trait Storage: Send + Sync {
+    /// Adds a tuple of predicate `name`, returning whether it was new
+    fn insert(&mut self, name: &str, tuple: &[Symbol]) -> bool;
+
+    /// Removes a tuple of predicate `name`, returning whether it was there
+    fn remove(&mut self, name: &str, tuple: &[Symbol]) -> bool;
+
+    fn contains(&self, name: &str, tuple: &[Symbol]) -> bool;
+
+    /// The tuples of predicate `name` with `arity` arguments
+    fn scan(&self, name: &str, arity: usize) -> Tuples<'_>;
+
+    /// The tuples of predicate `name` with `arity` arguments having, for each
+    /// `(column, symbol)` of `bound`, that symbol as that argument.
+    /// Called for every atom of a rule body with a constant or a bound
+    /// variable, so it should not scan the whole predicate.
+    fn lookup(&self, name: &str, arity: usize, bound: &[(usize, Symbol)]) -> Tuples<'_>;
+
+    /// Number of tuples of predicate `name` with `arity` arguments
+    fn count(&self, name: &str, arity: usize) -> usize;
+
+    /// Number of tuples of every predicate
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes every tuple, and the values given to `add_value`
+    fn clear(&mut self);
+
+    /// Called with each value the database interns, in the order of their
+    /// symbols, before a tuple holding it is inserted. Only a backend that
+    /// keeps its tuples after its database is gone needs them.
+    fn add_value(&mut self, _symbol: Symbol, _value: &Value) {}
+
+    /// The values given to `add_value`, by symbol, that a backend kept from
+    /// an earlier database. `Database::with_storage` starts from them when
+    /// the storage is not empty.
+    fn values(&self) -> Vec<Value> {
+        Vec::new()
+    }
+
+    /// The predicates having tuples, with their number of arguments
+    fn predicates(&self) -> Box<dyn Iterator<Item = (&str, usize)> + '_>;
+
+    /// An empty storage of the same kind, for the atoms derived by rules
+    fn empty(&self) -> Box<dyn Storage>;
+
+    /// Iterates over every tuple, with the name of its predicate
+    fn iter(&self) -> Box<dyn Iterator<Item = (&str, &[Symbol])> + '_> {
+        Box::new(
+            self.predicates().flat_map(move |(name, arity)| {
+                self.scan(name, arity).map(move |tuple| (name, tuple))
+            }),
+        )
+    }
+
+    /// Whether lookups on argument `column` of predicate `name` with `arity`
+    /// arguments go through an index built already
+    fn is_indexed(&self, _name: &str, _arity: usize, _column: usize) -> bool {
+        false
+    }
+}
+
+/// Tuples partitioned by predicate, so that looking up the tuples of one
+/// predicate does not depend on how many others there are.
+/// Their symbols belong to the table of the database holding the set.
+#[derive(Debug, Clone, Default)]
+pub struct AtomSet {
+    // By predicate name, then by number of arguments
+    relations: HashMap<String, HashMap<usize, Relation>>,
+    len: usize,
+}
+
+impl AtomSet {
+    pub fn new() -> Self {
+        AtomSet::default()
+    }
+
+    /// The tuples of predicate `name` with `arity` arguments, if there are any
+    pub fn relation(&self, name: &str, arity: usize) -> Option<&Relation> {
+        self.relations.get(name)?.get(&arity)
+    }
+}
+
+impl Storage for AtomSet {
+    fn insert(&mut self, name: &str, tuple: &[Symbol]) -> bool {
+        if !self.relations.contains_key(name) {
+            self.relations.insert(name.to_string(), HashMap::new());
+        }
+        let arity = tuple.len();
+        let added = self
+            .relations
+            .get_mut(name)
+            .expect("inserted above")
+            .entry(arity)
+            .or_insert_with(|| Relation::new(arity))
+            .insert(tuple);
+        if added {
+            self.len += 1;
+        }
+        added
+    }
+
+    fn remove(&mut self, name: &str, tuple: &[Symbol]) -> bool {
+        let Some(relations) = self.relations.get_mut(name) else {
+            return false;
+        };
+        let Some(relation) = relations.get_mut(&tuple.len()) else {
+            return false;
+        };
+        if !relation.remove(tuple) {
+            return false;
+        }
+        if relation.is_empty() {
+            relations.remove(&tuple.len());
+            if relations.is_empty() {
+                self.relations.remove(name);
+            }
+        }
+        self.len -= 1;
+        true
+    }
+
+    fn contains(&self, name: &str, tuple: &[Symbol]) -> bool {
+        self.relation(name, tuple.len())
+            .is_some_and(|relation| relation.contains(tuple))
+    }
+
+    fn scan(&self, name: &str, arity: usize) -> Tuples<'_> {
+        Box::new(
+            self.relation(name, arity)
+                .into_iter()
+                .flat_map(Relation::tuples),
+        )
+    }
+
+    // Looks up the first bound column through its index, and checks the
+    // others on the tuples found
+    fn lookup(&self, name: &str, arity: usize, bound: &[(usize, Symbol)]) -> Tuples<'_> {
+        let (Some(relation), Some(&(column, symbol))) = (self.relation(name, arity), bound.first())
+        else {
+            return self.scan(name, arity);
+        };
+        let rest = bound[1..].to_vec();
+        Box::new(
+            relation
+                .lookup(column, symbol)
+                .filter(move |tuple| rest.iter().all(|&(column, symbol)| tuple[column] == symbol)),
+        )
+    }
+
+    fn count(&self, name: &str, arity: usize) -> usize {
+        self.relation(name, arity).map_or(0, Relation::len)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn clear(&mut self) {
+        self.relations.clear();
+        self.len = 0;
+    }
+
+    fn predicates(&self) -> Box<dyn Iterator<Item = (&str, usize)> + '_> {
+        Box::new(self.relations.iter().flat_map(|(name, relations)| {
+            relations.keys().map(move |&arity| (name.as_str(), arity))
+        }))
+    }
+
+    fn empty(&self) -> Box<dyn Storage> {
+        Box::new(AtomSet::new())
+    }
+
+    fn is_indexed(&self, name: &str, arity: usize, column: usize) -> bool {
+        self.relation(name, arity)
+            .is_some_and(|relation| relation.is_indexed(column))
+    }
+}
+
+/// Writes the tuples of `storage` to a snapshot
+pub(crate) fn encode(storage: &dyn Storage, encoder: &mut Encoder) {
+    // Sorted so that the same atoms always give the same snapshot, whatever
+    // order they were derived in and whatever the storage
+    let mut predicates: Vec<(&str, usize)> = storage.predicates().collect();
+    predicates.sort();
+    encoder.len(predicates.len());
+    for (name, arity) in predicates {
+        encoder.str(name);
+        encoder.len(arity);
+        let mut tuples: Vec<&[Symbol]> = storage.scan(name, arity).collect();
+        tuples.sort();
+        encoder.len(tuples.len());
+        for symbol in tuples.concat() {
+            encoder.u32(symbol.0);
+        }
+    }
+}
+
+/// The symbol table of a storage that kept the values of its tuples, with
+/// the number of arguments of each of its predicates
+pub(crate) fn reopen(storage: &dyn Storage) -> Result<(Symbols, HashMap<String, usize>), String> {
+    let mut symbols = Symbols::new();
+    for value in storage.values() {
+        if symbols.get(&value).is_some() {
+            return Err(format!(
+                "value {} is twice in the storage",
+                value.to_source()
+            ));
+        }
+        symbols.intern(&value);
+    }
+    if let Some((name, _)) = storage.iter().find(|(_, tuple)| {
+        tuple
+            .iter()
+            .any(|symbol| symbol.0 as usize >= symbols.len())
+    }) {
+        return Err(format!("the storage does not have the values of {}", name));
+    }
+    let mut arities = HashMap::new();
+    for (name, arity) in storage.predicates() {
+        if let Some(other) = arities.insert(name.to_string(), arity) {
+            if other != arity {
+                return Err(format!(
+                    "{} has both {} and {} arguments in the storage",
+                    name, other, arity
+                ));
+            }
+        }
+    }
+    Ok((symbols, arities))
+}
+
+/// Reads tuples written by `encode` into `storage`. Their symbols must be
+/// symbols of `symbols`.
+pub(crate) fn decode(
+    decoder: &mut Decoder,
+    symbols: &Symbols,
+    storage: &mut dyn Storage,
+) -> io::Result<()> {
+    for _ in 0..decoder.len()? {
+        let name = decoder.str()?;
+        let arity = decoder.len()?;
+        let len = decoder.len()?;
+        if arity == 0 && len > 1 {
+            return Err(snapshot::invalid(format!(
+                "{} is in the snapshot {} times",
+                name, len
+            )));
+        }
+        let mut tuple = Vec::new();
+        for _ in 0..len {
+            tuple.clear();
+            for _ in 0..arity {
+                let id = decoder.u32()?;
+                if id as usize >= symbols.len() {
+                    return Err(snapshot::invalid(format!(
+                        "unknown symbol {} in the snapshot",
+                        id
+                    )));
+                }
+                tuple.push(Symbol(id));
+            }
+            storage.insert(name, &tuple);
+        }
+    }
+    Ok(())
+}
+
+/// The atoms of a `Storage`, read through the symbol table of its database
+#[derive(Clone, Copy)]
+pub struct Atoms<'a> {
+    storage: &'a dyn Storage,
+    symbols: &'a Symbols,
+}
+
+impl<'a> Atoms<'a> {
+    pub fn new(storage: &'a dyn Storage, symbols: &'a Symbols) -> Self {
+        Atoms { storage, symbols }
+    }
+
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
+    }
+
+    pub fn contains(&self, atom: &Atom) -> bool {
+        self.symbols
+            .get_atom(atom)
+            .is_some_and(|tuple| self.storage.contains(&atom.name, &tuple))
+    }
+
+    /// Number of atoms of predicate `name` with `arity` arguments
+    pub fn count(&self, name: &str, arity: usize) -> usize {
+        self.storage.count(name, arity)
+    }
+
+    /// Whether lookups on argument `column` of predicate `name` with `arity`
+    /// arguments go through an index built already
+    pub fn is_indexed(&self, name: &str, arity: usize, column: usize) -> bool {
+        self.storage.is_indexed(name, arity, column)
+    }
+
+    /// Iterates over the atoms, grouped by predicate
+    pub fn iter(&self) -> impl Iterator<Item = Atom> + 'a {
+        let symbols = self.symbols;
+        self.storage
+            .iter()
+            .map(move |(name, tuple)| symbols.resolve_atom(name, tuple))
+    }
+
+    /// Iterates over the tuples of predicate `name` with `arity` arguments
+    pub fn tuples_of(&self, name: &str, arity: usize) -> Tuples<'a> {
+        self.storage.scan(name, arity)
+    }
+
+    /// Iterates over the tuples of predicate `name` with `arity` arguments
+    /// that have `value` as argument `column`
+    pub fn tuples_where(
+        &self,
+        name: &str,
+        arity: usize,
+        column: usize,
+        value: &Value,
+    ) -> Tuples<'a> {
+        self.tuples_matching(name, arity, &[(column, value)])
+    }
+
+    /// Iterates over the tuples of predicate `name` with `arity` arguments
+    /// that have, for each `(column, value)` of `bound`, that value as that
+    /// argument
+    pub fn tuples_matching(
+        &self,
+        name: &str,
+        arity: usize,
+        bound: &[(usize, &Value)],
+    ) -> Tuples<'a> {
+        let symbols: Option<Vec<(usize, Symbol)>> = bound
+            .iter()
+            .map(|&(column, value)| Some((column, self.symbols.get(value)?)))
+            .collect();
+        match symbols {
+            Some(symbols) => self.lookup(name, arity, &symbols),
+            // A value that nothing stored has
+            None => Box::new(std::iter::empty()),
+        }
+    }
+
+    /// Like `tuples_matching`, with the symbols of the values
+    pub fn lookup(&self, name: &str, arity: usize, bound: &[(usize, Symbol)]) -> Tuples<'a> {
+        self.storage.lookup(name, arity, bound)
+    }
+
+    pub fn contains_tuple(&self, name: &str, tuple: &[Symbol]) -> bool {
+        self.storage.contains(name, tuple)
+    }
+}
+
+// Atoms are compared by value, so databases with different symbol tables
+// are equal when they hold the same atoms
+impl PartialEq for Atoms<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|atom| other.contains(&atom))
+    }
+}
+
+impl Eq for Atoms<'_> {}
+
+impl fmt::Debug for Atoms<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.iter().map(|atom| atom.to_string()))
+            .finish()
+    }
+}
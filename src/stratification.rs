@@ -0,0 +1,118 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::parser::{DatalogItem, Rule};
+
+/// An edge of the predicate dependency graph: the head of a rule depends on
+/// a predicate of its body, negatively if that body item is negated
+struct Dependency<'a> {
+    head: &'a str,
+    body: &'a str,
+    negated: bool,
+}
+
+fn item_name(item: &DatalogItem) -> Option<&str> {
+    match item {
+        DatalogItem::Atom(atom) => Some(&atom.name),
+        _ => None,
+    }
+}
+
+fn dependencies(rules: &[Rule]) -> Vec<Dependency<'_>> {
+    let mut dependencies = Vec::new();
+    for rule in rules {
+        for item in &rule.definition.relations {
+            let (item, negated) = match item {
+                DatalogItem::Negation(inner) => (inner.as_ref(), true),
+                item => (item, false),
+            };
+            if let Some(body) = item_name(item) {
+                dependencies.push(Dependency {
+                    head: &rule.head.name,
+                    body,
+                    negated,
+                });
+            }
+        }
+    }
+    dependencies
+}
+
+/// Shortest path from `from` to `to` following the dependency edges, if any
+fn find_path<'a>(
+    dependencies: &[Dependency<'a>],
+    from: &'a str,
+    to: &'a str,
+) -> Option<Vec<&'a str>> {
+    let mut previous: HashMap<&str, &str> = HashMap::new();
+    let mut visited = HashSet::from([from]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(current) = queue.pop_front() {
+        if current == to {
+            let mut path = vec![to];
+            let mut node = to;
+            while let Some(&before) = previous.get(node) {
+                path.push(before);
+                node = before;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for dependency in dependencies.iter().filter(|d| d.head == current) {
+            if visited.insert(dependency.body) {
+                previous.insert(dependency.body, current);
+                queue.push_back(dependency.body);
+            }
+        }
+    }
+    None
+}
+
+/// Groups the rules into strata so that every predicate used under a negation
+/// is fully computed before the rules that negate it are evaluated.
+/// Returns the indexes of the rules of each stratum, lowest stratum first,
+/// or an error naming the cycle if a predicate depends negatively on itself.
+pub fn stratify(rules: &[Rule]) -> Result<Vec<Vec<usize>>, String> {
+    let dependencies = dependencies(rules);
+
+    for dependency in dependencies.iter().filter(|d| d.negated) {
+        if let Some(path) = find_path(&dependencies, dependency.body, dependency.head) {
+            let mut cycle = vec![dependency.head];
+            cycle.extend(path);
+            return Err(format!(
+                "negation of {} inside the recursive cycle {}",
+                dependency.body,
+                cycle.join(" -> ")
+            ));
+        }
+    }
+
+    // Without negative cycles this settles in at most one pass per predicate
+    let mut strata: HashMap<&str, usize> = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for dependency in &dependencies {
+            let body = strata.get(dependency.body).copied().unwrap_or(0);
+            let required = if dependency.negated { body + 1 } else { body };
+            let head = strata.entry(dependency.head).or_insert(0);
+            if *head < required {
+                *head = required;
+                changed = true;
+            }
+        }
+    }
+
+    // Rules whose body only has comparisons never show up as a head
+    let stratum_of = |rule: &Rule| strata.get(rule.head.name.as_str()).copied().unwrap_or(0);
+    let count = rules
+        .iter()
+        .map(|rule| stratum_of(rule) + 1)
+        .max()
+        .unwrap_or(0);
+    let mut grouped = vec![Vec::new(); count];
+    for (index, rule) in rules.iter().enumerate() {
+        grouped[stratum_of(rule)].push(index);
+    }
+    grouped.retain(|stratum| !stratum.is_empty());
+    Ok(grouped)
+}
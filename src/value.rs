@@ -0,0 +1,185 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A constant stored in the database: `42`, `-1.5` or `"Mary Ann"`
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Value {
+    /// The value as it would be written in a Datalog program,
+    /// with strings quoted and escaped
+    pub fn to_source(&self) -> String {
+        match self {
+            Value::String(s) => {
+                let mut quoted = String::with_capacity(s.len() + 2);
+                quoted.push('"');
+                for c in s.chars() {
+                    match c {
+                        '"' => quoted.push_str("\\\""),
+                        '\\' => quoted.push_str("\\\\"),
+                        '\n' => quoted.push_str("\\n"),
+                        '\t' => quoted.push_str("\\t"),
+                        c => quoted.push(c),
+                    }
+                }
+                quoted.push('"');
+                quoted
+            }
+            other => other.to_string(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// "an integer", "a float" or "a string", for error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::String(_) => "a string",
+        }
+    }
+
+    /// Orders values as the comparison built-ins do: like `cmp`, except that
+    /// an integer and a float of the same numeric value are equal, so
+    /// `2 = 2.0` holds and `2.0 > 2` does not
+    pub fn compare(&self, other: &Value) -> Ordering {
+        let numeric = |a: f64, b: f64| a.partial_cmp(&b).unwrap_or_else(|| a.total_cmp(&b));
+        match (self, other) {
+            (Value::Integer(a), Value::Float(b)) => numeric(*a as f64, *b),
+            (Value::Float(a), Value::Integer(b)) => numeric(*a, *b as f64),
+            _ => self.cmp(other),
+        }
+    }
+
+    // Position of the variant in the ordering: numbers before strings
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Integer(_) | Value::Float(_) => 0,
+            Value::String(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{}", i),
+            // Keep a fractional part so floats read back as floats
+            Value::Float(x) if x.fract() == 0.0 && x.is_finite() => write!(f, "{:.1}", x),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+// Floats are compared by their bits so that values can be stored in hash sets.
+// Integers and floats are different values even when numerically equal.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a.to_bits() == b.to_bits(),
+            (Value::String(a), Value::String(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Integer(i) => i.hash(state),
+            Value::Float(x) => x.to_bits().hash(state),
+            Value::String(s) => s.hash(state),
+        }
+    }
+}
+
+// Numbers are ordered numerically, integers before floats of the same
+// magnitude, and all numbers come before strings. This total order sorts
+// answers; comparisons in queries use `Value::compare`.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (Value::Integer(a), Value::Float(b)) => (*a as f64).total_cmp(b).then(Ordering::Less),
+            (Value::Float(a), Value::Integer(b)) => {
+                a.total_cmp(&(*b as f64)).then(Ordering::Greater)
+            }
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+// Integer types that fit in an `i64`, so that struct fields of any of them
+// convert in `to_fact!`
+macro_rules! value_from_integer {
+    ($($type:ty),+) => {
+        $(impl From<$type> for Value {
+            fn from(i: $type) -> Self {
+                Value::Integer(i64::from(i))
+            }
+        })+
+    };
+}
+
+value_from_integer!(i8, i16, i32, u8, u16, u32);
+
+impl From<f32> for Value {
+    fn from(x: f32) -> Self {
+        Value::Float(f64::from(x))
+    }
+}
+
+// Lets tests and callers compare values with plain string literals
+impl PartialEq<&str> for Value {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == Some(*other)
+    }
+}
@@ -0,0 +1,27 @@
+// Helpers shared by the integration tests. Each test crate uses only some of
+// them.
+#![allow(dead_code)]
+
+use dataloglite::api::Database;
+use dataloglite::parser::{parse_datalog, Atom, DatalogItem, Term};
+
+/// Builds a database from the facts and rules of a program, which must parse
+/// completely. Queries are ignored and rules are not evaluated.
+pub fn load(input: &str) -> Database {
+    let (remaining, items) = parse_datalog(input).expect("Failed to parse");
+    assert_eq!(remaining, "");
+    let mut db = Database::new();
+    for item in items {
+        match item {
+            DatalogItem::Atom(atom) => db.add_atom(atom).expect("Invalid atom"),
+            DatalogItem::Rule(rule) => db.add_rule(rule).expect("Invalid rule"),
+            DatalogItem::Query(_) | DatalogItem::Comparison(_) | DatalogItem::Negation(_) => {}
+        }
+    }
+    db
+}
+
+/// A ground atom whose arguments are all strings
+pub fn atom(name: &str, args: &[&str]) -> Atom {
+    Atom::new(name, args.iter().map(|&arg| Term::constant(arg)).collect())
+}
@@ -0,0 +1,300 @@
+use dataloglite::api::Database;
+use dataloglite::convert::{ConversionError, FromRow, Row, ToFact};
+use dataloglite::parser::{Atom, Term};
+use dataloglite::query_engine::{Engine, Error};
+use dataloglite::to_fact;
+use dataloglite::value::Value;
+
+fn people() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .load(
+            r#"
+            age("Alice", 52).
+            age("Bob", 27).
+            height("Alice", 1.62).
+            height("Bob", 180).
+            nickname("Bob", "Bobby").
+        "#,
+        )
+        .unwrap();
+    engine
+}
+
+#[derive(Debug, PartialEq)]
+struct Person {
+    name: String,
+    age: i64,
+    height: f64,
+}
+
+impl FromRow for Person {
+    fn from_row(row: &Row) -> Result<Self, ConversionError> {
+        Ok(Person {
+            name: row.get_named("Name")?,
+            age: row.get_named("Age")?,
+            height: row.get_named("Height")?,
+        })
+    }
+}
+
+to_fact!(Person => person(name, age, height));
+
+struct Parent(&'static str, &'static str);
+
+to_fact!(Parent => parent(0, 1));
+
+#[test]
+fn test_query_as_tuples() {
+    let mut engine = people();
+
+    let ages: Vec<(String, i64)> = engine.query_as("?age(X, A).").unwrap();
+    assert_eq!(ages, [("Alice".to_string(), 52), ("Bob".to_string(), 27)]);
+
+    // Integers convert to floats
+    let heights: Vec<(String, f64)> = engine.query_as("?height(X, H).").unwrap();
+    assert_eq!(
+        heights,
+        [("Alice".to_string(), 1.62), ("Bob".to_string(), 180.0)]
+    );
+
+    let names: Vec<(Value,)> = engine.query_as(r#"?age(X, _), X != "Bob"."#).unwrap();
+    assert_eq!(names, [(Value::from("Alice"),)]);
+
+    // A query without variables has one empty row if it holds
+    let holds: Vec<()> = engine.query_as(r#"?age("Bob", 27)."#).unwrap();
+    assert_eq!(holds.len(), 1);
+}
+
+#[test]
+fn test_query_as_structs() {
+    let mut engine = people();
+    let people: Vec<Person> = engine
+        .query_as("?height(Name, Height), age(Name, Age).")
+        .unwrap();
+
+    assert_eq!(
+        people,
+        [
+            Person {
+                name: "Alice".to_string(),
+                age: 52,
+                height: 1.62
+            },
+            Person {
+                name: "Bob".to_string(),
+                age: 27,
+                height: 180.0
+            },
+        ]
+    );
+
+    let rows: Vec<Vec<Value>> = engine.database_mut().query_as("?age(X, A).").unwrap();
+    assert_eq!(rows[1], [Value::from("Bob"), Value::Integer(27)]);
+}
+
+#[test]
+fn test_query_as_column_count_mismatch() {
+    let mut engine = people();
+    let Err(Error::Conversion(error)) = engine.query_as::<(String,)>("?age(X, A).") else {
+        panic!("Expected Error::Conversion");
+    };
+    assert_eq!(
+        error.message,
+        "the answers have 2 columns (X, A), but 1 expected"
+    );
+
+    // Checked even without answers
+    let result = engine.query_as::<(String, i64, i64)>(r#"?age(X, A), X = "Nobody"."#);
+    assert!(matches!(result, Err(Error::Conversion(_))));
+}
+
+#[test]
+fn test_query_as_type_mismatch() {
+    let mut engine = people();
+
+    let Err(Error::Conversion(error)) = engine.query_as::<(String, String)>("?age(X, A).") else {
+        panic!("Expected Error::Conversion");
+    };
+    assert_eq!(
+        error.message,
+        r#"52 is an integer, expected a string for A in row ("Alice", 52)"#
+    );
+
+    let Err(Error::Conversion(error)) = engine.query_as::<(String, i64)>("?height(X, H).") else {
+        panic!("Expected Error::Conversion");
+    };
+    assert_eq!(
+        error.message,
+        r#"1.62 is a float, expected an integer for H in row ("Alice", 1.62)"#
+    );
+
+    let Err(Error::Conversion(error)) = engine.query_as::<Person>("?age(Name, Age).") else {
+        panic!("Expected Error::Conversion");
+    };
+    assert_eq!(
+        error.message,
+        r#"no variable Height in the answers in row ("Alice", 52)"#
+    );
+}
+
+#[test]
+fn test_query_as_query_errors() {
+    let mut engine = people();
+    assert!(matches!(
+        engine.query_as::<(String,)>("?age(X)."),
+        Err(Error::Statement(_))
+    ));
+    assert!(matches!(
+        engine.query_as::<(String,)>("?age(X"),
+        Err(Error::Syntax(_))
+    ));
+}
+
+#[test]
+fn test_insert_all_structs() {
+    let people = vec![
+        Person {
+            name: "Alice".to_string(),
+            age: 52,
+            height: 1.62,
+        },
+        Person {
+            name: "Bob".to_string(),
+            age: 27,
+            height: 180.0,
+        },
+    ];
+    assert_eq!(
+        people[0].to_atom().to_string(),
+        r#"person("Alice", 52, 1.62)"#
+    );
+
+    let mut db = Database::new();
+    db.insert_all(people.iter()).unwrap();
+    db.insert_all([Parent("Alice", "Bob")]).unwrap();
+
+    let rows: Vec<(String, i64)> = db.query_as("?person(N, A, _), A > 30.").unwrap();
+    assert_eq!(rows, [("Alice".to_string(), 52)]);
+    let back: Vec<Person> = db.query_as("?person(Name, Age, Height).").unwrap();
+    assert_eq!(back, people);
+    let rows: Vec<(String,)> = db.query_as(r#"?parent("Alice", C)."#).unwrap();
+    assert_eq!(rows, [("Bob".to_string(),)]);
+}
+
+#[test]
+fn test_insert_tuples() {
+    let mut db = Database::new();
+    db.insert_all([("parent", "Alice", "Bob"), ("parent", "Bob", "Carl")])
+        .unwrap();
+    db.insert(("born", "Alice".to_string(), 1970)).unwrap();
+    db.insert(("male", Value::from("Bob"))).unwrap();
+
+    let rows: Vec<(String, i64)> = db.query_as("?parent(X, \"Bob\"), born(X, Y).").unwrap();
+    assert_eq!(rows, [("Alice".to_string(), 1970)]);
+}
+
+#[test]
+fn test_insert_all_checks_arities() {
+    let mut db = Database::new();
+    db.declare("parent", 2).unwrap();
+
+    let error = db
+        .insert_all([("parent", "Alice", "Bob", "Carl")])
+        .unwrap_err();
+    assert_eq!(
+        error,
+        r#"parent has 2 arguments, but parent("Alice", "Bob", "Carl") is used with 3"#
+    );
+
+    // Facts are checked against each other, and nothing is added on a mismatch
+    let facts: [&dyn ToFact; 3] = [
+        &("person", "Alice", 52),
+        &("person", "Bob", 27),
+        &("person", "Carl"),
+    ];
+    let error = db.insert_all(facts).unwrap_err();
+    assert_eq!(
+        error,
+        r#"person has 2 arguments, but person("Carl") is used with 1"#
+    );
+    assert!(db.atoms().is_empty());
+    assert_eq!(db.arity("person"), None);
+
+    db.insert_all([("person", "Alice", 52)]).unwrap();
+    assert_eq!(
+        db.declare("person", 3).unwrap_err(),
+        "person has 2 arguments, but is declared with 3"
+    );
+    db.declare("person", 2).unwrap();
+}
+
+struct Reading {
+    sensor: u16,
+    celsius: i32,
+    kelvin: f32,
+}
+
+to_fact!(Reading => reading(sensor, celsius, kelvin));
+
+#[test]
+fn test_insert_smaller_numbers() {
+    let mut db = Database::new();
+    db.insert(Reading {
+        sensor: 7,
+        celsius: -3,
+        kelvin: 270.5,
+    })
+    .unwrap();
+    db.insert(("count", 3u8, 40_000u32)).unwrap();
+
+    let rows: Vec<(i64, i64, f64)> = db.query_as("?reading(S, C, K).").unwrap();
+    assert_eq!(rows, [(7, -3, 270.5)]);
+    let rows: Vec<(i64, i64)> = db.query_as("?count(X, Y).").unwrap();
+    assert_eq!(rows, [(3, 40_000)]);
+}
+
+#[test]
+fn test_predicate_names_are_checked() {
+    let mut db = Database::new();
+    for name in ["Parent", "has space", "", "p("] {
+        let message = format!("`{}` is not a predicate name", name);
+        assert_eq!(db.insert((name, "Alice")).unwrap_err(), message);
+        assert_eq!(db.insert_all([(name, "Alice")]).unwrap_err(), message);
+        assert_eq!(db.declare(name, 1).unwrap_err(), message);
+    }
+    assert!(db.atoms().is_empty());
+    assert_eq!(db.arity("Parent"), None);
+
+    db.insert(("parent_2", "Alice")).unwrap();
+}
+
+// Overrides `to_atom` to give an atom that is not a fact
+struct Open;
+
+impl ToFact for Open {
+    fn predicate(&self) -> &str {
+        "parent"
+    }
+
+    fn arguments(&self) -> Vec<Value> {
+        vec![Value::from("Alice")]
+    }
+
+    fn to_atom(&self) -> Atom {
+        Atom::new(
+            "parent",
+            vec![Term::constant("Alice"), Term::Var("X".to_string())],
+        )
+    }
+}
+
+#[test]
+fn test_facts_with_variables_are_rejected() {
+    let message = r#"variable X in fact `parent("Alice", X)` is never bound"#;
+    let mut db = Database::new();
+    assert_eq!(db.insert(Open).unwrap_err(), message);
+    assert_eq!(db.insert_all([Open]).unwrap_err(), message);
+    assert!(db.atoms().is_empty());
+    assert_eq!(db.arity("parent"), None);
+}
@@ -0,0 +1,143 @@
+use dataloglite::api::Database;
+use dataloglite::diagnostics::{parse_with_diagnostics, Diagnostic};
+use dataloglite::parser::DatalogItem;
+use indoc::indoc;
+
+#[test]
+fn test_every_syntax_error_is_reported() {
+    let input = include_str!("../test_examples/errors/syntax_errors.datalog");
+    let (items, diagnostics) = parse_with_diagnostics(input, "syntax_errors.datalog");
+
+    let positions: Vec<(usize, usize)> = diagnostics.iter().map(|d| (d.line, d.column)).collect();
+    assert_eq!(
+        positions,
+        [
+            (2, 16),
+            (3, 12),
+            (5, 32),
+            (7, 26),
+            (9, 28),
+            (10, 15),
+            (11, 1)
+        ]
+    );
+    // `female("Alice").` is skipped while recovering from the missing dot on line 3
+    assert_eq!(items.len(), 2);
+    assert!(matches!(&items[0], DatalogItem::Atom(atom) if atom.name == "parent"));
+    assert!(matches!(&items[1], DatalogItem::Query(_)));
+}
+
+#[test]
+fn test_diagnostic_display() {
+    let input = "parent(\"Alice\", \"Bob\").\nparent(\"Alice\" \"Julie\").\n";
+    let (_, diagnostics) = parse_with_diagnostics(input, "family.datalog");
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].to_string(),
+        indoc! {r#"
+            error: expected `,` or `)`, found `"`
+             --> family.datalog:2:16
+              |
+            2 | parent("Alice" "Julie").
+              |                ^"#}
+    );
+}
+
+#[test]
+fn test_expected_messages() {
+    for (input, message) in [
+        ("male(\"Bob\")", "expected `:-` or `.`, found end of input"),
+        ("?parent(X, Y) male(X).", "expected `.` or `,`, found `m`"),
+        (
+            "a(X) :- b(X), .",
+            "expected a predicate name, a string, a number, a variable or a parameter, found `.`",
+        ),
+        (
+            "a(X) :- b(X), X ! Y.",
+            "expected a comparison operator, found `!`",
+        ),
+        ("p(\"Mary).", "expected `\"`, found end of input"),
+        (
+            "Parent(\"Bob\").",
+            "expected a predicate name or `?`, found `P`",
+        ),
+    ] {
+        let (items, diagnostics) = parse_with_diagnostics(input, "test.datalog");
+        assert!(items.is_empty(), "{}", input);
+        assert_eq!(diagnostics.len(), 1, "{}", input);
+        assert_eq!(diagnostics[0].message, message, "{}", input);
+    }
+}
+
+#[test]
+fn test_recovery_ignores_dots_in_strings_numbers_and_comments() {
+    let input = indoc! {r#"
+        quote("Bob" "Hi. there", 1.5 /* . */ // .
+        ).
+        height("Bob", 1.8).
+    "#};
+    let (items, diagnostics) = parse_with_diagnostics(input, "test.datalog");
+
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(items.len(), 1);
+}
+
+#[test]
+fn test_strings_end_at_the_end_of_the_line() {
+    let input = "male(\"Bob).\nok(1).\nbad(1 2).\n?ok(X).\n";
+    let (items, diagnostics) = parse_with_diagnostics(input, "test.datalog");
+
+    let errors: Vec<(usize, usize, &str)> = diagnostics
+        .iter()
+        .map(|d| (d.line, d.column, d.message.as_str()))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (1, 12, "expected `\"`, found end of line"),
+            (3, 7, "expected `,` or `)`, found `2`")
+        ]
+    );
+    // The statement with the unterminated string ends at the next dot
+    assert_eq!(items.len(), 1);
+    assert!(matches!(&items[0], DatalogItem::Query(_)));
+}
+
+#[test]
+fn test_columns_count_characters() {
+    let (_, diagnostics) = parse_with_diagnostics("city(\"São Paulo\" 1).", "test.datalog");
+
+    assert_eq!(diagnostics[0].column, 18);
+}
+
+#[test]
+fn test_runtime_errors_point_at_the_statement() {
+    let input = indoc! {r#"
+        parent("Alice", "Bob").
+          parent(X) :- male(X).
+    "#};
+    let (items, diagnostics) = parse_with_diagnostics(input, "family.datalog");
+    assert!(diagnostics.is_empty());
+
+    let mut db = Database::new();
+    let mut errors = Vec::new();
+    for item in items {
+        match item {
+            DatalogItem::Atom(atom) => db.add_atom(atom).unwrap(),
+            DatalogItem::Rule(rule) => {
+                let span = rule.span;
+                if let Err(e) = db.add_rule(rule) {
+                    errors.push(Diagnostic::at("family.datalog", input, span, e));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].line, errors[0].column), (2, 3));
+    assert!(errors[0]
+        .to_string()
+        .ends_with("2 |   parent(X) :- male(X).\n  |   ^"));
+}
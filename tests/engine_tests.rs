@@ -0,0 +1,271 @@
+use std::io::{self, Write};
+use std::thread;
+
+use dataloglite::api::QueryResult;
+use dataloglite::datalog;
+use dataloglite::query_engine::{Engine, Error};
+use dataloglite::value::Value;
+use indoc::indoc;
+
+const FAMILY: &str = indoc! {r#"
+    parent("Alice", "Bob").
+    parent("Bob", "Charlie").
+    ancestor(X, Y) :- parent(X, Y).
+    ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+"#};
+
+#[test]
+fn test_load_and_query() {
+    let mut engine = Engine::new();
+    engine.load(FAMILY).unwrap();
+
+    let QueryResult::Rows(answers) = engine.query(r#"?ancestor("Alice", D)."#).unwrap() else {
+        panic!("Expected QueryResult::Rows");
+    };
+    assert_eq!(answers.variables, ["D"]);
+    assert_eq!(
+        answers.rows,
+        [[Value::from("Bob")], [Value::from("Charlie")]]
+    );
+
+    let result = engine.query(r#"?ancestor("Alice", "Charlie")."#).unwrap();
+    assert_eq!(result, QueryResult::Bool(true));
+
+    let result = engine
+        .query("?parent(_, _), parent(_, \"Alice\").")
+        .unwrap();
+    assert_eq!(result, QueryResult::Bool(false));
+}
+
+#[test]
+fn test_engines_are_independent() {
+    let handles: Vec<_> = ["Bob", "Julie"]
+        .into_iter()
+        .map(|child| {
+            thread::spawn(move || {
+                let mut engine = Engine::new();
+                engine
+                    .load(&format!(r#"parent("Alice", "{}")."#, child))
+                    .unwrap();
+                engine.query(r#"?parent("Alice", C)."#).unwrap()
+            })
+        })
+        .collect();
+
+    let rows: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    let rows: Vec<_> = rows
+        .into_iter()
+        .map(|result| match result {
+            QueryResult::Rows(answers) => answers.rows,
+            QueryResult::Bool(_) => panic!("Expected QueryResult::Rows"),
+        })
+        .collect();
+    assert_eq!(rows, [[[Value::from("Bob")]], [[Value::from("Julie")]]]);
+}
+
+#[test]
+fn test_run_writes_output() {
+    let mut engine = Engine::new();
+    let mut output = Vec::new();
+    engine
+        .run(r#"male("Bob"). ?male(_)."#, &mut output)
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "male is Bob\nQuery: list all where male(_)\nBob\n"
+    );
+}
+
+#[test]
+fn test_syntax_errors_run_nothing() {
+    let mut engine = Engine::new();
+    let mut output = Vec::new();
+    let error = engine
+        .run("male(\"Bob\").\nmale(\"Charlie\".\nmale(.", &mut output)
+        .unwrap_err();
+
+    let Error::Syntax(diagnostics) = error else {
+        panic!("Expected Error::Syntax");
+    };
+    assert_eq!(diagnostics.len(), 2);
+    assert!(output.is_empty());
+    assert!(engine.database().atoms().is_empty());
+}
+
+#[test]
+fn test_rejected_statement_stops_the_program() {
+    let mut engine = Engine::new();
+    let mut output = Vec::new();
+    let error = engine
+        .run_file(
+            "parent(\"Alice\", \"Bob\").\nparent(\"Bob\").\nmale(\"Bob\").",
+            "family.datalog",
+            &mut output,
+        )
+        .unwrap_err();
+
+    let Error::Statement(diagnostic) = error else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!(diagnostic.file, "family.datalog");
+    assert_eq!((diagnostic.line, diagnostic.column), (2, 1));
+    assert_eq!(
+        diagnostic.message,
+        r#"parent has 2 arguments, but parent("Bob") is used with 1"#
+    );
+    // The first fact was added, the last one never ran
+    assert_eq!(engine.database().atoms().len(), 1);
+}
+
+#[test]
+fn test_unsafe_rule_is_reported_at_the_rule() {
+    let mut engine = Engine::new();
+    let mut output = Vec::new();
+    let error = engine
+        .run_file(
+            "parent(\"Alice\", \"Bob\").\n  child(X, Y) :- parent(X, Z).",
+            "family.datalog",
+            &mut output,
+        )
+        .unwrap_err();
+
+    let Error::Statement(diagnostic) = error else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!((diagnostic.line, diagnostic.column), (2, 3));
+    assert_eq!(
+        diagnostic.message,
+        "variable Y in head `child(X, Y)` is never bound"
+    );
+    assert!(engine.database().rules().is_empty());
+}
+
+#[test]
+fn test_load_rejects_queries() {
+    let mut engine = Engine::new();
+    let error = engine
+        .load("male(\"Bob\").\n?male(_).\nmale(\"Charlie\").")
+        .unwrap_err();
+
+    let Error::Statement(diagnostic) = error else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!(diagnostic.line, 2);
+    assert_eq!(engine.database().atoms().len(), 1);
+}
+
+#[test]
+fn test_query_expects_a_single_query() {
+    let mut engine = Engine::new();
+    engine.load(FAMILY).unwrap();
+
+    for input in [
+        r#"parent("Alice", "Bob")."#,
+        "?parent(X, Y). ?parent(Y, X).",
+        "",
+    ] {
+        let error = engine.query(input).unwrap_err();
+        assert!(
+            matches!(error, Error::Statement(_)),
+            "{:?} gave {:?}",
+            input,
+            error
+        );
+    }
+    assert!(matches!(
+        engine.query("?parent(X Y)."),
+        Err(Error::Syntax(_))
+    ));
+    assert!(matches!(
+        engine.query("?parent(X)."),
+        Err(Error::Statement(_))
+    ));
+}
+
+struct FailingWriter;
+
+impl Write for FailingWriter {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_errors_are_returned() {
+    let mut engine = Engine::new();
+    let error = engine
+        .run(r#"male("Bob")."#, &mut FailingWriter)
+        .unwrap_err();
+    assert!(matches!(error, Error::Io(_)));
+}
+
+fn panic_message(program: impl FnOnce() + std::panic::UnwindSafe) -> String {
+    let payload = std::panic::catch_unwind(program).unwrap_err();
+    payload.downcast_ref::<String>().unwrap().clone()
+}
+
+#[test]
+fn test_datalog_macro() {
+    let mut db = datalog! {
+        parent("Alice", "Bob").
+        parent("Bob", "Carl").
+        age("Alice", -52).
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y), !age(Y, 1.5), X != "Dave".
+    };
+    let rows: Vec<(String,)> = db.query_as(r#"?ancestor(X, "Carl")."#).unwrap();
+    assert_eq!(rows, [("Alice".to_string(),), ("Bob".to_string(),)]);
+    assert!(db.query(r#"?age("Alice", -52)."#).unwrap().holds());
+
+    let mut db = datalog! {r#"
+        male("Bob").
+        male("Carl").
+    "#};
+    assert_eq!(db.atoms().len(), 2);
+    assert!(db.query(r#"?male("Bob")."#).unwrap().holds());
+}
+
+#[test]
+fn test_datalog_macro_panics_at_the_error() {
+    let line = line!() + 4;
+    let message = panic_message(|| {
+        datalog! {r#"
+        parent("Alice", "Bob").
+        parent("Bob" "Carl").
+    "#};
+    });
+    assert!(
+        message.contains(&format!("--> {}:{}:22", file!(), line)),
+        "{}",
+        message
+    );
+    assert!(message.contains(r#"parent("Bob" "Carl")."#), "{}", message);
+
+    // Tokens lose their lines, so errors only name the line of the macro
+    let line = line!() + 2;
+    let message = panic_message(|| {
+        datalog! {
+            parent("Alice", "Bob").
+            parent("Bob").
+        };
+    });
+    assert_eq!(
+        message,
+        format!(
+            "invalid datalog! program\nerror: {}\n --> {}:{}",
+            r#"parent has 2 arguments, but parent("Bob") is used with 1"#,
+            file!(),
+            line
+        )
+    );
+
+    let message = panic_message(|| {
+        datalog! { male("Bob"). ?male(X). };
+    });
+    assert!(message.contains("queries can not be loaded"), "{}", message);
+}
@@ -0,0 +1,393 @@
+use dataloglite::api::Database;
+use dataloglite::parser::{parse_datalog, Atom, DatalogItem, Rule, Term};
+
+mod common;
+
+use common::{atom, load};
+
+fn rule(input: &str) -> Rule {
+    let (_, mut items) = parse_datalog(input).expect("Failed to parse");
+    let Some(DatalogItem::Rule(rule)) = items.pop() else {
+        panic!("Expected Rule variant");
+    };
+    rule
+}
+
+#[test]
+fn test_semi_naive_matches_naive_on_cousins_full() {
+    let input = include_str!("../test_examples/parser/cousins_full.datalog");
+
+    let mut naive = load(input);
+    naive.evaluate_rules_naive();
+    let mut semi_naive = load(input);
+    semi_naive.evaluate_rules();
+
+    assert_eq!(naive.derived_atoms(), semi_naive.derived_atoms());
+    assert!(semi_naive.contains_atom(&atom("sibling", &["Bob", "Barbara"])));
+    assert!(!semi_naive.contains_atom(&atom("sibling", &["Bob", "Bob"])));
+    assert!(semi_naive.contains_atom(&atom("uncle", &["Bob", "David"])));
+    assert!(semi_naive.contains_atom(&atom("cousin", &["Charlie", "Diana"])));
+    assert!(semi_naive.contains_atom(&atom("grandmother", &["Alice", "Cindy"])));
+}
+
+#[test]
+fn test_semi_naive_derives_cousins() {
+    let input = r#"
+        parent("Alice", "Bob").
+        parent("Alice", "Barbara").
+        parent("Bob", "Charlie").
+        parent("Barbara", "David").
+        parent("Barbara", "Diana").
+        sibling("Bob", "Barbara").
+        sibling("Barbara", "Bob").
+        cousin(X, Y) :- parent(A, X), parent(B, Y), sibling(A, B).
+        grandparent(X, Y) :- parent(X, Z), parent(Z, Y).
+    "#;
+
+    let mut naive = load(input);
+    naive.evaluate_rules_naive();
+    let mut semi_naive = load(input);
+    semi_naive.evaluate_rules();
+
+    assert_eq!(naive.derived_atoms(), semi_naive.derived_atoms());
+    let mut derived: Vec<String> = semi_naive
+        .derived_atoms()
+        .iter()
+        .map(|atom| atom.to_string())
+        .collect();
+    derived.sort();
+    assert_eq!(
+        derived,
+        [
+            r#"cousin("Charlie", "David")"#,
+            r#"cousin("Charlie", "Diana")"#,
+            r#"cousin("David", "Charlie")"#,
+            r#"cousin("Diana", "Charlie")"#,
+            r#"grandparent("Alice", "Charlie")"#,
+            r#"grandparent("Alice", "David")"#,
+            r#"grandparent("Alice", "Diana")"#,
+        ]
+    );
+}
+
+#[test]
+fn test_semi_naive_matches_naive_on_recursive_chain() {
+    let person = |i: u8| format!("P{}", i);
+    let mut input = String::new();
+    for i in 0..25 {
+        input.push_str(&format!(
+            "parent(\"{}\", \"{}\").\n",
+            person(i),
+            person(i + 1)
+        ));
+    }
+    input.push_str("ancestor(X, Y) :- parent(X, Y).\n");
+    input.push_str("ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).\n");
+
+    let mut naive = load(&input);
+    naive.evaluate_rules_naive();
+    let mut semi_naive = load(&input);
+    semi_naive.evaluate_rules();
+
+    // 26 people in a line: every pair (i, j) with i < j is an ancestor pair
+    assert_eq!(semi_naive.derived_atoms().len(), 26 * 25 / 2);
+    assert_eq!(naive.derived_atoms(), semi_naive.derived_atoms());
+}
+
+#[test]
+fn test_semi_naive_mutual_recursion() {
+    let input = r#"
+        edge("A", "B").
+        edge("B", "C").
+        edge("C", "D").
+        odd(X, Y) :- edge(X, Y).
+        odd(X, Y) :- edge(X, Z), even(Z, Y).
+        even(X, Y) :- edge(X, Z), odd(Z, Y).
+    "#;
+
+    let mut naive = load(input);
+    naive.evaluate_rules_naive();
+    let mut semi_naive = load(input);
+    semi_naive.evaluate_rules();
+
+    assert_eq!(naive.derived_atoms(), semi_naive.derived_atoms());
+    assert!(semi_naive.contains_atom(&atom("odd", &["A", "D"])));
+    assert!(semi_naive.contains_atom(&atom("even", &["A", "C"])));
+    assert!(!semi_naive.contains_atom(&atom("even", &["A", "D"])));
+}
+
+#[test]
+fn test_evaluation_picks_up_new_relations() {
+    let mut db = load("ancestor(X, Y) :- parent(X, Y).");
+    db.evaluate_rules();
+    assert!(db.derived_atoms().is_empty());
+
+    db.add_atom(atom("parent", &["Alice", "Bob"])).unwrap();
+    db.evaluate_rules();
+    assert!(db.contains_atom(&atom("ancestor", &["Alice", "Bob"])));
+}
+
+#[test]
+fn test_rule_with_unbound_comparison_variable() {
+    let mut db = Database::new();
+    let error = db
+        .add_rule(rule("older(X, Y) :- parent(X, Y), X > Z."))
+        .unwrap_err();
+    assert_eq!(error, "variable Z in comparison `X > Z` is never bound");
+    assert!(db.rules().is_empty());
+}
+
+#[test]
+fn test_rule_with_unbound_negated_variable() {
+    let mut db = Database::new();
+    let error = db
+        .add_rule(rule("childless(X, Y) :- parent(Y, X), !parent(X, Z)."))
+        .unwrap_err();
+    assert_eq!(
+        error,
+        "variable Z in negated `!parent(X, Z)` is never bound"
+    );
+}
+
+#[test]
+fn test_stratified_negation_evaluates_negated_predicate_first() {
+    // The rule using negation comes first, but `reach` must be complete before it runs
+    let input = r#"
+        node("A").
+        node("B").
+        node("C").
+        edge("A", "B").
+        edge("B", "C").
+        unreachable(X, Y) :- node(X), node(Y), X != Y, !reach(X, Y).
+        reach(X, Y) :- edge(X, Y).
+        reach(X, Y) :- edge(X, Z), reach(Z, Y).
+    "#;
+
+    let mut naive = load(input);
+    naive.evaluate_rules_naive();
+    let mut semi_naive = load(input);
+    semi_naive.evaluate_rules();
+
+    assert_eq!(naive.derived_atoms(), semi_naive.derived_atoms());
+    assert!(semi_naive.contains_atom(&atom("reach", &["A", "C"])));
+    assert!(!semi_naive.contains_atom(&atom("unreachable", &["A", "C"])));
+    assert!(semi_naive.contains_atom(&atom("unreachable", &["C", "A"])));
+    assert!(semi_naive.contains_atom(&atom("unreachable", &["B", "A"])));
+}
+
+#[test]
+fn test_negation_inside_recursive_cycle_is_rejected() {
+    let mut db = Database::new();
+    db.add_rule(rule("winner(X, Y) :- move(X, Y), !loser(X, Y)."))
+        .unwrap();
+    let error = db
+        .add_rule(rule("loser(X, Y) :- winner(Y, X)."))
+        .unwrap_err();
+
+    assert_eq!(
+        error,
+        "negation of loser inside the recursive cycle winner -> loser -> winner"
+    );
+    // The offending rule is not kept
+    assert_eq!(db.rules().len(), 1);
+}
+
+#[test]
+fn test_direct_negative_recursion_is_rejected() {
+    let mut db = Database::new();
+    let error = db
+        .add_rule(rule("odd(X, Y) :- edge(X, Y), !odd(Y, X)."))
+        .unwrap_err();
+
+    assert_eq!(
+        error,
+        "negation of odd inside the recursive cycle odd -> odd"
+    );
+}
+
+#[test]
+fn test_atom_with_different_arity_is_rejected() {
+    let mut db = Database::new();
+    db.add_atom(atom("parent", &["Alice", "Bob"])).unwrap();
+    let error = db
+        .add_atom(Atom::new(
+            "parent",
+            ["Alice", "Bob", "Paris"]
+                .map(|arg| Term::Const(arg.into()))
+                .to_vec(),
+        ))
+        .unwrap_err();
+
+    assert_eq!(
+        error,
+        r#"parent has 2 arguments, but parent("Alice", "Bob", "Paris") is used with 3"#
+    );
+    assert_eq!(db.atoms().len(), 1);
+}
+
+#[test]
+fn test_unsafe_rules_are_rejected() {
+    let mut db = load(r#"parent("Alice", "Bob")."#);
+
+    let error = db
+        .add_rule(rule("grandparent(X, Z) :- parent(X, Y)."))
+        .unwrap_err();
+    assert_eq!(
+        error,
+        "variable Z in head `grandparent(X, Z)` is never bound"
+    );
+
+    // Negated atoms do not bind variables
+    let error = db
+        .add_rule(rule("orphan(X) :- !parent(_, X)."))
+        .unwrap_err();
+    assert_eq!(
+        error,
+        "variable X in negated `!parent(_, X)` is never bound"
+    );
+    let error = db
+        .add_rule(rule("loner(X) :- male(Y), !parent(X, Y)."))
+        .unwrap_err();
+    assert_eq!(
+        error,
+        "variable X in negated `!parent(X, Y)` is never bound"
+    );
+
+    let error = db.add_rule(rule("child(_) :- parent(_, Y).")).unwrap_err();
+    assert_eq!(error, "`_` in head `child(_)` has no value");
+    assert!(db.rules().is_empty());
+}
+
+#[test]
+fn test_rule_with_different_arity_is_rejected() {
+    let mut db = load(r#"parent("Alice", "Bob")."#);
+
+    let error = db.add_rule(rule("parent(X) :- male(X).")).unwrap_err();
+    assert_eq!(
+        error,
+        "parent has 2 arguments, but parent(X) is used with 1"
+    );
+
+    // Body atoms are checked against each other too
+    let error = db
+        .add_rule(rule("odd(X) :- edge(X, Y), edge(X)."))
+        .unwrap_err();
+    assert_eq!(error, "edge has 2 arguments, but edge(X) is used with 1");
+    assert!(db.rules().is_empty());
+    assert_eq!(db.arity("edge"), None);
+}
+
+#[test]
+fn test_rules_of_any_arity() {
+    let input = r#"
+        born("Alice", "Ohio", "Paris").
+        born("Bob", "Texas", "Paris").
+        raining.
+        same_city(X, Y, C) :- born(X, A, C), born(Y, B, C), X != Y.
+        wet :- raining.
+    "#;
+    let mut db = load(input);
+    db.evaluate_rules();
+
+    assert!(db.contains_atom(&Atom::new(
+        "same_city",
+        ["Alice", "Bob", "Paris"]
+            .map(|arg| Term::Const(arg.into()))
+            .to_vec(),
+    )));
+    assert!(db.contains_atom(&atom("wet", &[])));
+    assert_eq!(db.arity("same_city"), Some(3));
+}
+
+#[test]
+fn test_numbers_compare_numerically() {
+    let input = r#"
+        reading("A", 9).
+        reading("B", 10).
+        reading("C", 9.5).
+        reading("D", "10").
+        high(X) :- reading(X, V), V > 9.
+        exact(X) :- reading(X, 10).
+    "#;
+    let mut db = load(input);
+    db.evaluate_rules();
+
+    // Strings sort after numbers, and 10 is not "10"
+    assert!(!db.contains_atom(&atom("high", &["A"])));
+    assert!(db.contains_atom(&atom("high", &["B"])));
+    assert!(db.contains_atom(&atom("high", &["C"])));
+    assert!(db.contains_atom(&atom("high", &["D"])));
+    assert!(db.contains_atom(&atom("exact", &["B"])));
+    assert!(!db.contains_atom(&atom("exact", &["D"])));
+}
+
+#[test]
+fn test_integers_and_floats_of_the_same_value_compare_equal() {
+    let input = r#"
+        h("a", 2.0).
+        h("b", 2).
+        h("c", 3).
+        above(X) :- h(X, H), H > 2.
+        above_float(X) :- h(X, H), H > 2.0.
+        at_least(X) :- h(X, H), H >= 2.0.
+        below(X) :- h(X, H), H < 2.5.
+        at_most(X) :- h(X, H), H <= 2.
+        equal(X) :- h(X, H), H = 2.
+        other(X) :- h(X, H), H != 2.0.
+        same(X, Y) :- h(X, H), h(Y, G), X != Y, H = G.
+    "#;
+    let mut db = load(input);
+    db.evaluate_rules();
+
+    let derived = |name: &str| -> Vec<String> {
+        ["a", "b", "c"]
+            .into_iter()
+            .filter(|x| db.contains_atom(&atom(name, &[x])))
+            .map(String::from)
+            .collect()
+    };
+    assert_eq!(derived("above"), ["c"]);
+    assert_eq!(derived("above_float"), ["c"]);
+    assert_eq!(derived("at_least"), ["a", "b", "c"]);
+    assert_eq!(derived("below"), ["a", "b"]);
+    assert_eq!(derived("at_most"), ["a", "b"]);
+    assert_eq!(derived("equal"), ["a", "b"]);
+    assert_eq!(derived("other"), ["c"]);
+    assert!(db.contains_atom(&atom("same", &["a", "b"])));
+    assert_eq!(db.derived_atoms().count("same", 2), 2);
+}
+
+#[test]
+fn test_fact_with_variable_is_rejected() {
+    let mut db = Database::new();
+    let (_, items) = parse_datalog(r#"parent(X, "Bob")."#).unwrap();
+    let Some(DatalogItem::Atom(fact)) = items.into_iter().next() else {
+        panic!("Expected Atom variant");
+    };
+    let error = db.add_atom(fact).unwrap_err();
+    assert_eq!(
+        error,
+        r#"variable X in fact `parent(X, "Bob")` is never bound"#
+    );
+}
+
+#[test]
+fn test_rule_with_constants_and_anonymous_variables() {
+    let input = r#"
+        parent("Alice", "Bob").
+        parent("Bob", "Charlie").
+        parent("Diana", "Eve").
+        child_of_alice(X) :- parent("Alice", X).
+        has_parent(X) :- parent(_, X).
+        grandparent(X) :- parent(X, Y), parent(Y, _).
+    "#;
+    let mut db = load(input);
+    db.evaluate_rules();
+
+    assert!(db.contains_atom(&atom("child_of_alice", &["Bob"])));
+    assert!(!db.contains_atom(&atom("child_of_alice", &["Eve"])));
+    assert!(db.contains_atom(&atom("has_parent", &["Eve"])));
+    assert!(!db.contains_atom(&atom("has_parent", &["Alice"])));
+    assert!(db.contains_atom(&atom("grandparent", &["Alice"])));
+    assert!(!db.contains_atom(&atom("grandparent", &["Bob"])));
+}
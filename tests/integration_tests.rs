@@ -1,5 +1,5 @@
 use dataloglite::{
-    parser::{parse_datalog, DatalogItem, RuleDefinition},
+    parser::{parse_datalog, DatalogItem, RuleDefinition, Term},
     query_engine::interpret,
 };
 use indoc::indoc;
@@ -17,18 +17,18 @@ fn test_example_datalog() {
 
     // Test some sample relations
     assert!(relations.iter().any(|r| {
-        if let DatalogItem::Relation(rel) = r {
-            rel.first == "Alice" && rel.second == "Bob"
+        if let DatalogItem::Atom(rel) = r {
+            rel.args == [Term::constant("Alice"), Term::constant("Bob")]
         } else {
             false
         }
     }));
     assert!(relations.iter().any(|r| match r {
-        DatalogItem::Relation(rel) => rel.first == "Bob" && rel.second == "Charlie",
+        DatalogItem::Atom(rel) => rel.args == [Term::constant("Bob"), Term::constant("Charlie")],
         _ => false,
     }));
     assert!(relations.iter().any(|r| match r {
-        DatalogItem::Relation(rel) => rel.first == "Diana" && rel.second == "Henry",
+        DatalogItem::Atom(rel) => rel.args == [Term::constant("Diana"), Term::constant("Henry")],
         _ => false,
     }));
 }
@@ -45,7 +45,7 @@ fn test_empty_input() {
 #[test]
 fn test_example_comments() {
     let input = include_str!("../test_examples/parser/comments.datalog");
-    let (remaining, relations) = parse_datalog(&input).expect("Failed to parse");
+    let (remaining, relations) = parse_datalog(input).expect("Failed to parse");
 
     assert_eq!(remaining, "");
     assert_eq!(relations.len(), 10);
@@ -54,50 +54,48 @@ fn test_example_comments() {
 #[test]
 fn test_cousins_facts_rules() {
     let input = include_str!("../test_examples/parser/cousins_facts_rules.datalog");
-    let (remaining, items) = parse_datalog(&input).expect("Failed to parse");
+    let (remaining, items) = parse_datalog(input).expect("Failed to parse");
 
     assert_eq!(remaining, "");
     assert_eq!(items.len(), 12);
 
     // Verify some parent relations
     assert!(items.iter().any(|r| match r {
-        DatalogItem::Relation(rel) => rel.first == "Alice" && rel.second == "Bob",
+        DatalogItem::Atom(rel) => rel.args == [Term::constant("Alice"), Term::constant("Bob")],
         _ => false,
     }));
     assert!(items.iter().any(|r| match r {
-        DatalogItem::Relation(rel) => rel.first == "Alice" && rel.second == "Barbara",
+        DatalogItem::Atom(rel) => rel.args == [Term::constant("Alice"), Term::constant("Barbara")],
         _ => false,
     }));
     assert!(items.iter().any(|r| match r {
-        DatalogItem::Relation(rel) => rel.first == "Diana" && rel.second == "Henry",
+        DatalogItem::Atom(rel) => rel.args == [Term::constant("Diana"), Term::constant("Henry")],
         _ => false,
     }));
     // print!("Parsed items: {:#?}", items);
     let DatalogItem::Rule(el) = &items[items.len() - 1] else {
         panic!("Expected Rule variant");
     };
-    assert_eq!(el.name, "father");
-    assert_eq!(el.first, "X");
-    assert_eq!(el.second, "Y");
+    assert_eq!(el.head.name, "father");
+    assert_eq!(el.head.args, [Term::var("X"), Term::var("Y")]);
     assert_eq!(el.definition.relations.len(), 2);
 
-    let RuleDefinition { relations } = &el.definition;
+    let RuleDefinition { relations, .. } = &el.definition;
     assert_eq!(relations.len(), 2);
 
     // Check first relation
-    let DatalogItem::Relation(rel) = &relations[0] else {
-        panic!("Expected Relation");
+    let DatalogItem::Atom(rel) = &relations[0] else {
+        panic!("Expected Atom");
     };
     assert_eq!(rel.name, "parent");
-    assert_eq!(rel.first, "X");
-    assert_eq!(rel.second, "Y");
+    assert_eq!(rel.args, [Term::var("X"), Term::var("Y")]);
 
     // Check fact
-    let DatalogItem::Fact(rel) = &relations[1] else {
-        panic!("Expected Fact");
+    let DatalogItem::Atom(rel) = &relations[1] else {
+        panic!("Expected Atom");
     };
     assert_eq!(rel.name, "male");
-    assert_eq!(rel.first, "X");
+    assert_eq!(rel.args, [Term::var("X")]);
 }
 
 #[test]
@@ -105,7 +103,7 @@ fn test_query_relation() {
     let input = include_str!("../test_examples/queries/basic_relation.datalog");
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -124,7 +122,7 @@ fn test_query_variable_based_relation_second_is_var() {
     );
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -141,7 +139,7 @@ fn test_query_variable_based_relation_first_is_var() {
         include_str!("../test_examples/queries/variable_based_relation_query_first_is_var.datalog");
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -160,7 +158,7 @@ fn test_query_recursive_siblings_first_is_var() {
     );
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -177,7 +175,7 @@ fn test_query_rule_father() {
     let input = include_str!("../test_examples/queries/rule_father.datalog");
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -188,8 +186,8 @@ fn test_query_rule_father() {
         parent is Bob of Charlie
         parent is Bob of Cindy
         father of X, Y means parent(X, Y), male(X)
-        Query: Who is father of Charlie?"};
-    // TODO: answer query
+        Query: Who is father of Charlie?
+        Bob"};
     assert_eq!(output.trim(), expected_output)
 }
 
@@ -198,7 +196,7 @@ fn test_query_fact() {
     let input = include_str!("../test_examples/queries/basic_fact.datalog");
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -216,7 +214,7 @@ fn test_query_basic_projection_relation() {
     let input = include_str!("../test_examples/queries/basic_projection_relation.datalog");
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -229,7 +227,9 @@ fn test_query_basic_projection_relation() {
         parent is Alice of Julie
         parent is Charlie of Julie
         Query: list all where parent(X, _)
-        Bob, Julie"};
+        X
+        Alice
+        Charlie"};
     assert_eq!(output.trim(), expected_output)
 }
 
@@ -238,7 +238,7 @@ fn test_query_basic_projection_fact() {
     let input = include_str!("../test_examples/queries/basic_projection_fact.datalog");
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
     let expected_output = indoc! {"
@@ -260,10 +260,9 @@ fn test_query_conjunctive() {
     let input = include_str!("../test_examples/queries/basic_conjunctive.datalog");
 
     let mut buffer = Vec::new();
-    interpret(&input, &mut buffer, Some(true));
+    interpret(input, &mut buffer).unwrap();
     let output = String::from_utf8(buffer).expect("Failed to convert output to string");
 
-    // TODO: fix. It should be Charlie
     let expected_output = indoc! {"
         male is Charlie
         male is Bob
@@ -276,6 +275,323 @@ fn test_query_conjunctive() {
         Query: list all where:
             parent(X, Y)
             male(X)
+        X       | Y
+        Charlie | Bob
+        Charlie | Julie"};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_query_rule_ancestor_recursive() {
+    let input = include_str!("../test_examples/queries/rule_ancestor.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        parent is Alice of Bob
+        parent is Bob of Charlie
+        parent is Charlie of Dave
+        ancestor of X, Y means parent(X, Y)
+        ancestor of X, Y means parent(X, Z), ancestor(Z, Y)
+        Query: Who is ancestor of Dave?
+        Alice, Bob, Charlie
+        Query: Of whom is Alice ancestor?
+        Bob, Charlie, Dave
+        Query: ancestor is Alice of Dave?
+        true
+        Query: ancestor is Dave of Alice?
+        false"};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_query_rule_with_constant_in_body() {
+    let input = indoc! {r#"
+        parent("Alice", "Bob").
+        parent("Bob", "Carl").
+        parent("Dave", "Eve").
+        parent("Eve", "Fred").
+        grandchild(X, Y) :- parent("Alice", X), parent(X, Y).
+        ?grandchild("Bob", "Carl").
+        ?grandchild("Eve", "Fred").
+    "#};
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {r#"
+        parent is Alice of Bob
+        parent is Bob of Carl
+        parent is Dave of Eve
+        parent is Eve of Fred
+        grandchild of X, Y means parent("Alice", X), parent(X, Y)
+        Query: grandchild is Bob of Carl?
+        true
+        Query: grandchild is Eve of Fred?
+        false"#};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_parse_cousins_full() {
+    let input = include_str!("../test_examples/parser/cousins_full.datalog");
+    let (remaining, items) = parse_datalog(input).expect("Failed to parse");
+
+    assert_eq!(remaining, "");
+    assert_eq!(items.len(), 32);
+
+    let Some(DatalogItem::Rule(sibling)) = items
+        .iter()
+        .find(|item| matches!(item, DatalogItem::Rule(rule) if rule.head.name == "sibling"))
+    else {
+        panic!("Expected sibling rule");
+    };
+    let DatalogItem::Comparison(comparison) = &sibling.definition.relations[2] else {
+        panic!("Expected Comparison");
+    };
+    assert_eq!(comparison.to_string(), "X != Y");
+}
+
+#[test]
+fn test_query_comparison() {
+    let input = include_str!("../test_examples/queries/comparison.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        parent is Alice of Bob
+        parent is Alice of Julie
+        parent is Alice of Tom
+        sibling of X, Y means parent(Z, X), parent(Z, Y), X != Y
+        Query: Who is sibling of Bob?
+        Julie, Tom
+        Query: list all where:
+            sibling(X, Y)
+            X < \"Tom\"
+        X     | Y
+        Bob   | Julie
+        Bob   | Tom
+        Julie | Bob
+        Julie | Tom"};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_query_negation() {
+    let input = include_str!("../test_examples/queries/negation.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        male is Bob
+        male is Charlie
+        parent is Alice of Bob
+        parent is Bob of Charlie
+        parent is Diana of Charlie
+        mother of X, Y means parent(X, Y), not male(X)
+        Query: Who is mother of Charlie?
+        Diana
+        Query: list all where:
+            parent(X, Y)
+            not male(X)
+        X     | Y
+        Alice | Bob
+        Diana | Charlie"};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_query_atoms_of_any_arity() {
+    let input = include_str!("../test_examples/queries/arity.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        born(Alice, Ohio, Paris)
+        born(Bob, Texas, Lyon)
+        raining
+        wet means raining
+        parisian of X means born(X, Y, Z), Z = \"Paris\"
+        Query: born(Alice, Ohio, Paris)?
+        true
+        Query: wet?
+        true
+        Query: parisian is Alice
+        true
+        Query: parisian is Bob
+        false"};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_query_typed_values() {
+    let input = include_str!("../test_examples/queries/values.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        born is Mary Ann of 1970
+        born is São Paulo Jr of 1985
+        born is O'Brien of 2001
+        height is Mary Ann of 1.62
+        quote is O'Brien of said \"hi\"
+        veteran of X means born(X, Y), Y < 2000
+        Query: born is Mary Ann of 1970?
+        true
+        Query: list all where veteran(_)
+        Mary Ann, São Paulo Jr
+        Query: list all where:
+            born(X, Y)
+            Y > 1980
+        X            | Y
+        O'Brien      | 2001
+        São Paulo Jr | 1985
+        Query: list all where:
+            height(X, Y)
+            Y > 1.5
+        X        | Y
+        Mary Ann | 1.62
+        Query: Of whom is O'Brien quote?
+        said \"hi\""};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_query_general_variables() {
+    let input = include_str!("../test_examples/queries/general_variables.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        male is Bob
+        parent is Alice of Bob
+        parent is Bob of Charlie
+        parent is Bob of Cindy
+        Query: Who is parent of Charlie?
+        Bob
+        Query: Of whom is Bob parent?
+        Charlie, Cindy
+        Query: list all where parent(_, Child)
+        Child
+        Bob
+        Charlie
+        Cindy
+        Query: list all where:
+            parent(P, C)
+            male(P)
+        P   | C
+        Bob | Charlie
+        Bob | Cindy
+        Query: list all where:
+            parent(_, Person)
+            parent(Person, _)
+        Person
+        Bob"};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_query_join() {
+    let input = include_str!("../test_examples/queries/join.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        male is Bob
+        parent is Alice of Bob
+        parent is Bob of Charlie
+        parent is Bob of Cindy
+        Query: list all where:
+            parent(X, Y)
+            parent(Y, Z)
+        X     | Y   | Z
+        Alice | Bob | Charlie
+        Alice | Bob | Cindy
+        Query: list all where:
+            parent(X, \"Charlie\")
+            male(X)
+        X
         Bob"};
     assert_eq!(output.trim(), expected_output)
 }
+
+#[test]
+fn test_query_quoted_constants() {
+    let input = include_str!("../test_examples/queries/quoted_constants.datalog");
+
+    let mut buffer = Vec::new();
+    interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        parent is X of Bob
+        parent is Alice of Y
+        Query: parent is X of Bob?
+        true
+        Query: parent is X of Y?
+        false
+        Query: Who is parent of Bob?
+        X
+        Query: list all where:
+            parent(X, Y)
+            parent(X, \"Bob\")
+        X | Y
+        X | Bob"};
+    assert_eq!(output.trim(), expected_output)
+}
+
+#[test]
+fn test_statements_after_syntax_errors_still_run() {
+    let input = include_str!("../test_examples/errors/syntax_errors.datalog");
+
+    let mut buffer = Vec::new();
+    let diagnostics = interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        parent is Alice of Bob
+        Query: Who is parent of Bob?
+        Alice"};
+    assert_eq!(output.trim(), expected_output);
+    assert_eq!(diagnostics.len(), 7);
+}
+
+#[test]
+fn test_rejected_query_prints_nothing() {
+    let input = indoc! {r#"
+        parent("Alice", "Bob").
+        ?parent(X).
+        ?parent(X, "Bob").
+    "#};
+
+    let mut buffer = Vec::new();
+    let diagnostics = interpret(input, &mut buffer).unwrap();
+    let output = String::from_utf8(buffer).expect("Failed to convert output to string");
+
+    let expected_output = indoc! {"
+        parent is Alice of Bob
+        Query: Who is parent of Bob?
+        Alice"};
+    assert_eq!(output.trim(), expected_output);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 1));
+    assert_eq!(
+        diagnostics[0].message,
+        "parent has 2 arguments, but parent(X) is used with 1"
+    );
+}
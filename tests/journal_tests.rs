@@ -0,0 +1,229 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+use dataloglite::convert::ToFact;
+use dataloglite::journal::{Error, JournaledDatabase};
+use dataloglite::parser::{parse_rule, Atom, Term};
+use dataloglite::value::Value;
+
+// A snapshot and a journal path of their own for each test
+fn paths(name: &str) -> (PathBuf, PathBuf) {
+    let directory = std::env::temp_dir().join(format!(
+        "dataloglite-journal-{}-{}",
+        std::process::id(),
+        name
+    ));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    (
+        directory.join("family.snapshot"),
+        directory.join("family.journal"),
+    )
+}
+
+fn names(db: &mut JournaledDatabase, query: &str) -> Vec<String> {
+    let rows: Vec<(String,)> = db.query_as(query).unwrap();
+    rows.into_iter().map(|(name,)| name).collect()
+}
+
+#[test]
+fn test_journal_is_replayed_on_open() {
+    let (snapshot, journal) = paths("replay");
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    db.insert_all([("parent", "Alice", "Bob"), ("parent", "Bob", "Carl")])
+        .unwrap();
+    db.insert(("parent", "Carl", "Dave")).unwrap();
+    let (_, rule) = parse_rule("ancestor(X, Y) :- parent(X, Y).").unwrap();
+    db.add_rule(rule).unwrap();
+    let (_, rule) = parse_rule("ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).").unwrap();
+    db.add_rule(rule).unwrap();
+    assert!(db.retract(("parent", "Bob", "Carl")).unwrap());
+    assert!(!db.retract(("parent", "Bob", "Nobody")).unwrap());
+    drop(db);
+
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    assert_eq!(db.database().atoms().len(), 2);
+    assert_eq!(db.database().rules().len(), 2);
+    assert_eq!(names(&mut db, r#"?ancestor("Alice", X)."#), ["Bob"]);
+    assert!(!snapshot.exists());
+}
+
+// A fact that is not ground, which a `ToFact` can give by overriding `to_atom`
+struct Open(Atom);
+
+impl ToFact for Open {
+    fn predicate(&self) -> &str {
+        &self.0.name
+    }
+
+    fn arguments(&self) -> Vec<Value> {
+        Vec::new()
+    }
+
+    fn to_atom(&self) -> Atom {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn test_rejected_changes_are_not_journaled() {
+    let (snapshot, journal) = paths("rejected");
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    db.insert(("parent", "Alice", "Bob")).unwrap();
+    let len = fs::metadata(&journal).unwrap().len();
+
+    let Err(Error::Rejected(message)) = db.insert(("parent", "Alice")) else {
+        panic!("Expected Error::Rejected");
+    };
+    assert_eq!(
+        message,
+        r#"parent has 2 arguments, but parent("Alice") is used with 1"#
+    );
+    let Err(Error::Rejected(message)) = db.insert(("Parent", "Alice", "Bob")) else {
+        panic!("Expected Error::Rejected");
+    };
+    assert_eq!(message, "`Parent` is not a predicate name");
+    let open = Atom::new(
+        "parent",
+        vec![Term::constant("Alice"), Term::Var("X".into())],
+    );
+    let Err(Error::Rejected(message)) = db.insert(Open(open)) else {
+        panic!("Expected Error::Rejected");
+    };
+    assert_eq!(
+        message,
+        r#"variable X in fact `parent("Alice", X)` is never bound"#
+    );
+    let (_, rule) = parse_rule("orphan(X) :- !parent(_, X).").unwrap();
+    assert!(matches!(db.add_rule(rule), Err(Error::Rejected(_))));
+    assert_eq!(fs::metadata(&journal).unwrap().len(), len);
+}
+
+#[test]
+fn test_torn_last_record_is_dropped() {
+    let (snapshot, journal) = paths("torn");
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    db.insert(("parent", "Alice", "Bob")).unwrap();
+    db.insert(("parent", "Bob", "Carl")).unwrap();
+    drop(db);
+
+    // A crash in the middle of writing the last record
+    let full = fs::metadata(&journal).unwrap().len();
+    let file = OpenOptions::new().write(true).open(&journal).unwrap();
+    file.set_len(full - 5).unwrap();
+    drop(file);
+
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    assert_eq!(names(&mut db, "?parent(X, _)."), ["Alice"]);
+    db.insert(("parent", "Carl", "Dave")).unwrap();
+    drop(db);
+
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    assert_eq!(names(&mut db, "?parent(X, _)."), ["Alice", "Carl"]);
+}
+
+#[test]
+fn test_damaged_record_is_an_error() {
+    let (snapshot, journal) = paths("damaged");
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    db.insert(("parent", "Alice", "Bob")).unwrap();
+    db.insert(("parent", "Bob", "Carl")).unwrap();
+    drop(db);
+
+    let mut bytes = fs::read(&journal).unwrap();
+    bytes[30] ^= 0x01;
+    fs::write(&journal, &bytes).unwrap();
+    let Err(error) = JournaledDatabase::open(&snapshot, &journal) else {
+        panic!("Expected a damaged journal");
+    };
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "journal record at byte 12 is damaged");
+
+    fs::write(&journal, "parent(\"Alice\", \"Bob\").\n").unwrap();
+    let Err(error) = JournaledDatabase::open(&snapshot, &journal) else {
+        panic!("Expected an invalid journal");
+    };
+    assert_eq!(error.to_string(), "not a dataloglite journal");
+
+    // Part of a header is damage too, while an empty journal is started again
+    fs::write(&journal, "DLLJRNL").unwrap();
+    let Err(error) = JournaledDatabase::open(&snapshot, &journal) else {
+        panic!("Expected an invalid journal");
+    };
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    fs::write(&journal, "").unwrap();
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    assert!(db.database().atoms().is_empty());
+    assert!(!db.retract(("parent", "Alice", "Bob")).unwrap());
+}
+
+#[test]
+fn test_damaged_length_is_an_error() {
+    let (snapshot, journal) = paths("damaged-length");
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    for child in ["Bob", "Carl", "Dave", "Eve", "Fred"] {
+        db.insert(("parent", "Alice", child)).unwrap();
+    }
+    drop(db);
+
+    // The length of the second record now points past the end of the journal
+    let mut bytes = fs::read(&journal).unwrap();
+    let first = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+    let second = 12 + 12 + first;
+    bytes[second + 3] ^= 0x01;
+    fs::write(&journal, &bytes).unwrap();
+    let Err(error) = JournaledDatabase::open(&snapshot, &journal) else {
+        panic!("Expected a damaged journal");
+    };
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        format!("journal record at byte {} is damaged", second)
+    );
+    // The records after it are still there
+    assert_eq!(fs::read(&journal).unwrap(), bytes);
+}
+
+#[test]
+fn test_compact_empties_the_journal() {
+    let (snapshot, journal) = paths("compact");
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    let header = fs::metadata(&journal).unwrap().len();
+    db.insert_all([("parent", "Alice", "Bob"), ("parent", "Bob", "Carl")])
+        .unwrap();
+    let (_, rule) = parse_rule("grandparent(X, Z) :- parent(X, Y), parent(Y, Z).").unwrap();
+    db.add_rule(rule).unwrap();
+    db.compact().unwrap();
+    assert_eq!(fs::metadata(&journal).unwrap().len(), header);
+    db.insert(("parent", "Carl", "Dave")).unwrap();
+    drop(db);
+
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    assert_eq!(db.database().derived_atoms().len(), 1);
+    assert_eq!(names(&mut db, "?grandparent(X, _)."), ["Alice", "Bob"]);
+}
+
+#[test]
+fn test_journal_replays_over_its_compacted_snapshot() {
+    let (snapshot, journal) = paths("crash-in-compact");
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    db.insert(("parent", "Alice", "Bob")).unwrap();
+    db.insert(("parent", "Bob", "Carl")).unwrap();
+    assert!(db.retract(("parent", "Alice", "Bob")).unwrap());
+    let (_, rule) = parse_rule("child(Y) :- parent(_, Y).").unwrap();
+    db.add_rule(rule).unwrap();
+    let records = fs::read(&journal).unwrap();
+
+    // A crash after the snapshot is replaced but before the journal is emptied
+    db.compact().unwrap();
+    drop(db);
+    let mut file = OpenOptions::new().append(true).open(&journal).unwrap();
+    file.write_all(&records[12..]).unwrap();
+    drop(file);
+
+    let mut db = JournaledDatabase::open(&snapshot, &journal).unwrap();
+    assert_eq!(db.database().rules().len(), 1);
+    assert_eq!(names(&mut db, "?parent(X, _)."), ["Bob"]);
+    assert_eq!(names(&mut db, "?child(X)."), ["Carl"]);
+}
@@ -1,23 +1,22 @@
 use dataloglite::parser::{
-    parse_query, NonQueryDatalogItem, QueryProjection, VariableBasedRelation,
+    parse_query, NonQueryDatalogItem, QueryProjection, Term, VariableBasedRelation,
 };
 
 #[test]
 fn test_parse_query_relation() {
     let input = r#"?parent("Alice", "Bob")."#;
     let (remaining, query) = parse_query(input).unwrap();
-    let NonQueryDatalogItem::Relation(el) = query.data else {
-        panic!("Expected NonQueryDatalogItem::Relation");
+    let NonQueryDatalogItem::Atom(el) = query.data else {
+        panic!("Expected NonQueryDatalogItem::Atom");
     };
     assert_eq!(remaining, "");
     assert_eq!(el.name, "parent");
-    assert_eq!(el.first, "Alice");
-    assert_eq!(el.second, "Bob");
+    assert_eq!(el.args, [Term::constant("Alice"), Term::constant("Bob")]);
 }
 
 #[test]
 fn test_parse_query_variable_based_relation_first_is_var() {
-    let input = r#"?parent("X", "Bob")."#;
+    let input = r#"?parent(X, "Bob")."#;
     let (remaining, query) = parse_query(input).unwrap();
     let NonQueryDatalogItem::VariableBasedRelation(vbr) = query.data else {
         panic!("Expected NonQueryDatalogItem::VariableBasedRelation");
@@ -34,7 +33,7 @@ fn test_parse_query_variable_based_relation_first_is_var() {
 
 #[test]
 fn test_parse_query_variable_based_relation_second_is_var() {
-    let input = r#"?parent("Alice", "X")."#;
+    let input = r#"?parent("Alice", X)."#;
     let (remaining, query) = parse_query(input).unwrap();
     let NonQueryDatalogItem::VariableBasedRelation(vbr) = query.data else {
         panic!("Expected NonQueryDatalogItem::VariableBasedRelation");
@@ -60,8 +59,8 @@ fn test_parse_query_projection_relation_first_unknown() {
 
     assert_eq!(remaining, "");
     assert_eq!(q.name, "parent");
-    assert_eq!(q.first, "_");
-    assert_eq!(q.second, "Y");
+    assert_eq!(q.first, Term::var("_"));
+    assert_eq!(q.second, Term::var("Y"));
 }
 
 #[test]
@@ -75,8 +74,8 @@ fn test_parse_query_projection_relation_second_unknown() {
 
     assert_eq!(remaining, "");
     assert_eq!(q.name, "parent");
-    assert_eq!(q.first, "X");
-    assert_eq!(q.second, "_");
+    assert_eq!(q.first, Term::var("X"));
+    assert_eq!(q.second, Term::var("_"));
 }
 
 #[test]
@@ -96,12 +95,12 @@ fn test_parse_query_projection_fact() {
 fn test_parse_query_fact() {
     let input = r#"?female("Alice")."#;
     let (remaining, query) = parse_query(input).unwrap();
-    let NonQueryDatalogItem::Fact(el) = query.data else {
-        panic!("Expected NonQueryDatalogItem::Fact");
+    let NonQueryDatalogItem::Atom(el) = query.data else {
+        panic!("Expected NonQueryDatalogItem::Atom");
     };
     assert_eq!(remaining, "");
     assert_eq!(el.name, "female");
-    assert_eq!(el.first, "Alice");
+    assert_eq!(el.args, [Term::constant("Alice")]);
 }
 
 #[test]
@@ -116,17 +115,97 @@ fn test_parse_conjunctive_query() {
         panic!("Expected ConjunctiveQuery");
     };
 
-    let QueryProjection::QueryProjectionRelation(rel) = &el.data[0] else {
-        panic!("Expected QueryProjectionRelation");
+    let QueryProjection::Atom(rel) = &el.data[0] else {
+        panic!("Expected Atom");
     };
     assert_eq!(rel.name, "parent");
-    assert_eq!(rel.first, "X");
-    assert_eq!(rel.second, "Y");
+    assert_eq!(rel.args, [Term::var("X"), Term::var("Y")]);
 
-    let QueryProjection::QueryProjectionFact(fact) = &el.data[1] else {
-        panic!("Expected QueryProjectionFact");
+    let QueryProjection::Atom(fact) = &el.data[1] else {
+        panic!("Expected Atom");
     };
     assert_eq!(fact.name, "male");
-    // we are only testing the name because it can only be X
-    // if this assumption changes we will need to test for it
+    assert_eq!(fact.args, [Term::var("X")]);
+}
+
+#[test]
+fn test_parse_query_atoms_of_any_arity() {
+    let (remaining, query) = parse_query(r#"?born("Alice", "Ohio", "Paris")."#).unwrap();
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::Atom(el) = query.data else {
+        panic!("Expected NonQueryDatalogItem::Atom");
+    };
+    assert_eq!(
+        el.args,
+        [
+            Term::constant("Alice"),
+            Term::constant("Ohio"),
+            Term::constant("Paris")
+        ]
+    );
+
+    let (remaining, query) = parse_query("?raining.").unwrap();
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::Atom(el) = query.data else {
+        panic!("Expected NonQueryDatalogItem::Atom");
+    };
+    assert_eq!(el.name, "raining");
+    assert!(el.args.is_empty());
+}
+
+#[test]
+fn test_parse_conjunctive_query_with_comparison() {
+    let input = r#"?parent(X, Y), X != "Bob"."#;
+    let (remaining, query) = parse_query(input).unwrap();
+    assert_eq!(remaining, "");
+
+    let NonQueryDatalogItem::ConjunctiveQuery(el) = query.data else {
+        panic!("Expected ConjunctiveQuery");
+    };
+    assert_eq!(el.data.len(), 2);
+    let QueryProjection::Comparison(comparison) = &el.data[1] else {
+        panic!("Expected Comparison");
+    };
+    assert_eq!(comparison.to_string(), r#"X != "Bob""#);
+}
+
+#[test]
+fn test_parse_query_with_named_variables() {
+    let (remaining, query) = parse_query(r#"?parent(Who, "Bob")."#).unwrap();
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::VariableBasedRelation(
+        VariableBasedRelation::VariableBasedRelationFirstIsVar(el),
+    ) = query.data
+    else {
+        panic!("Expected VariableBasedRelationFirstIsVar variant");
+    };
+    assert_eq!(el.variable, "Who");
+    assert_eq!(el.second, "Bob");
+
+    let (remaining, query) = parse_query("?parent(_, Child_1).").unwrap();
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::QueryProjectionRelation(q) = query.data else {
+        panic!("Expected NonQueryDatalogItem::QueryProjectionRelation");
+    };
+    assert_eq!(q.first, Term::var("_"));
+    assert_eq!(q.second, Term::var("Child_1"));
+
+    let (remaining, query) = parse_query("?parent(P, C), male(P), parent(_, P).").unwrap();
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::ConjunctiveQuery(q) = query.data else {
+        panic!("Expected NonQueryDatalogItem::ConjunctiveQuery");
+    };
+    // `_` is not reported as a variable
+    assert_eq!(q.variables(), ["P", "C"]);
+}
+
+#[test]
+fn test_parse_query_quoted_x_is_a_constant() {
+    let (remaining, query) = parse_query(r#"?parent("X", "Bob")."#).unwrap();
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::Atom(el) = query.data else {
+        panic!("Expected NonQueryDatalogItem::Atom");
+    };
+    assert_eq!(el.args, [Term::constant("X"), Term::constant("Bob")]);
+    assert_eq!(el.variables().count(), 0);
 }
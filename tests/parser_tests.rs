@@ -1,14 +1,54 @@
-use dataloglite::parser::{parse_datalog, parse_relation, parse_rule_definition};
-use dataloglite::parser::{DatalogItem, RuleDefinition};
+use std::collections::HashSet;
+
+use dataloglite::diagnostics::Span;
+use dataloglite::parser::{
+    parse_atom, parse_comparison, parse_datalog, parse_rule, parse_rule_definition,
+};
+use dataloglite::parser::{
+    Atom, ComparisonOperator, DatalogItem, NonQueryDatalogItem, QueryProjection, RuleDefinition,
+    Term,
+};
+use dataloglite::value::Value;
 
 #[test]
 fn test_parse_single_relation() {
     let input = r#"parent("Alice", "Bob")."#;
-    let (remaining, relation) = parse_relation(input).unwrap();
+    let (remaining, relation) = parse_atom(input).unwrap();
     assert_eq!(remaining, "");
     assert_eq!(relation.name, "parent");
-    assert_eq!(relation.first, "Alice");
-    assert_eq!(relation.second, "Bob");
+    assert_eq!(
+        relation.args,
+        [Term::constant("Alice"), Term::constant("Bob")]
+    );
+}
+
+#[test]
+fn test_parse_atoms_of_any_arity() {
+    let input = r#"born("Alice", "Ohio", "Paris"). raining. sunny()."#;
+    let (remaining, items) = parse_datalog(input).unwrap();
+    assert_eq!(remaining, "");
+    assert_eq!(items.len(), 3);
+
+    let DatalogItem::Atom(born) = &items[0] else {
+        panic!("Expected Atom variant");
+    };
+    assert_eq!(born.name, "born");
+    assert_eq!(
+        born.args,
+        [
+            Term::constant("Alice"),
+            Term::constant("Ohio"),
+            Term::constant("Paris")
+        ]
+    );
+
+    for (item, name) in items[1..].iter().zip(["raining", "sunny"]) {
+        let DatalogItem::Atom(atom) = item else {
+            panic!("Expected Atom variant");
+        };
+        assert_eq!(atom.name, name);
+        assert_eq!(atom.arity(), 0);
+    }
 }
 
 #[test]
@@ -18,20 +58,18 @@ fn test_parse_multiple_relations() {
     assert_eq!(remaining, "");
     assert_eq!(relations.len(), 2);
     match &relations[0] {
-        DatalogItem::Relation(rel) => {
+        DatalogItem::Atom(rel) => {
             assert_eq!(rel.name, "parent");
-            assert_eq!(rel.first, "A");
-            assert_eq!(rel.second, "B");
+            assert_eq!(rel.args, [Term::constant("A"), Term::constant("B")]);
         }
-        _ => panic!("Expected Relation variant"),
+        _ => panic!("Expected Atom variant"),
     }
     match &relations[1] {
-        DatalogItem::Relation(rel) => {
+        DatalogItem::Atom(rel) => {
             assert_eq!(rel.name, "mother");
-            assert_eq!(rel.first, "B");
-            assert_eq!(rel.second, "C");
+            assert_eq!(rel.args, [Term::constant("B"), Term::constant("C")]);
         }
-        _ => panic!("Expected Relation variant"),
+        _ => panic!("Expected Atom variant"),
     }
 }
 
@@ -41,11 +79,11 @@ fn test_parse_fact() {
     let (remaining, fact) = parse_datalog(input).unwrap();
     assert_eq!(remaining, "");
     match &fact[0] {
-        DatalogItem::Fact(f) => {
+        DatalogItem::Atom(f) => {
             assert_eq!(f.name, "male");
-            assert_eq!(f.first, "X");
+            assert_eq!(f.args, [Term::var("X")]);
         }
-        _ => panic!("Expected Fact variant"),
+        _ => panic!("Expected Atom variant"),
     }
 }
 
@@ -56,19 +94,18 @@ fn test_parse_rule_definition() {
     assert_eq!(remaining, "");
     assert_eq!(rule_definition.relations.len(), 2);
     match &rule_definition.relations[0] {
-        DatalogItem::Relation(rel) => {
+        DatalogItem::Atom(rel) => {
             assert_eq!(rel.name, "parent");
-            assert_eq!(rel.first, "X");
-            assert_eq!(rel.second, "Y");
+            assert_eq!(rel.args, [Term::var("X"), Term::var("Y")]);
         }
-        _ => panic!("Expected Relation"),
+        _ => panic!("Expected Atom"),
     };
     match &rule_definition.relations[1] {
-        DatalogItem::Fact(rel) => {
+        DatalogItem::Atom(rel) => {
             assert_eq!(rel.name, "male");
-            assert_eq!(rel.first, "X");
+            assert_eq!(rel.args, [Term::var("X")]);
         }
-        _ => panic!("Expected Fact"),
+        _ => panic!("Expected Atom"),
     };
 }
 
@@ -82,26 +119,234 @@ fn test_parse_rule() {
         panic!("Expected Rule variant");
     };
 
-    assert_eq!(el.name, "father");
-    assert_eq!(el.first, "X");
-    assert_eq!(el.second, "Y");
+    assert_eq!(el.head.name, "father");
+    assert_eq!(el.head.args, [Term::var("X"), Term::var("Y")]);
     assert_eq!(el.definition.relations.len(), 2);
 
-    let RuleDefinition { relations } = &el.definition;
+    let RuleDefinition { relations, .. } = &el.definition;
     assert_eq!(relations.len(), 2);
 
     // Check first relation
-    let DatalogItem::Relation(rel) = &relations[0] else {
-        panic!("Expected Relation");
+    let DatalogItem::Atom(rel) = &relations[0] else {
+        panic!("Expected Atom");
     };
     assert_eq!(rel.name, "parent");
-    assert_eq!(rel.first, "X");
-    assert_eq!(rel.second, "Y");
+    assert_eq!(rel.args, [Term::var("X"), Term::var("Y")]);
 
     // Check fact
-    let DatalogItem::Fact(rel) = &relations[1] else {
-        panic!("Expected Fact");
+    let DatalogItem::Atom(rel) = &relations[1] else {
+        panic!("Expected Atom");
     };
     assert_eq!(rel.name, "male");
-    assert_eq!(rel.first, "X");
+    assert_eq!(rel.args, [Term::var("X")]);
+}
+
+#[test]
+fn test_parse_comparison() {
+    let input = r#"X != "Bob""#;
+    let (remaining, comparison) = parse_comparison(input).unwrap();
+    assert_eq!(remaining, "");
+    assert_eq!(comparison.left, Term::var("X"));
+    assert_eq!(comparison.operator, ComparisonOperator::NotEqual);
+    assert_eq!(comparison.right, Term::constant("Bob"));
+}
+
+#[test]
+fn test_parse_comparison_operators() {
+    for (input, operator) in [
+        ("X = Y", ComparisonOperator::Equal),
+        ("X != Y", ComparisonOperator::NotEqual),
+        ("X < Y", ComparisonOperator::LessThan),
+        ("X <= Y", ComparisonOperator::LessOrEqual),
+        ("X > Y", ComparisonOperator::GreaterThan),
+        ("X >= Y", ComparisonOperator::GreaterOrEqual),
+    ] {
+        let (remaining, comparison) = parse_comparison(input).unwrap();
+        assert_eq!(remaining, "");
+        assert_eq!(comparison.operator, operator);
+        assert_eq!(comparison.to_string(), input);
+    }
+}
+
+#[test]
+fn test_parse_rule_with_comparison() {
+    let input = r#"sibling(X, Y) :- parent(Z, X), parent(Z, Y), X != Y."#;
+    let (remaining, items) = parse_datalog(input).unwrap();
+    assert_eq!(remaining, "");
+
+    let DatalogItem::Rule(el) = &items[0] else {
+        panic!("Expected Rule variant");
+    };
+    assert_eq!(el.definition.relations.len(), 3);
+    let DatalogItem::Comparison(comparison) = &el.definition.relations[2] else {
+        panic!("Expected Comparison");
+    };
+    assert_eq!(comparison.left, Term::var("X"));
+    assert_eq!(comparison.operator, ComparisonOperator::NotEqual);
+    assert_eq!(comparison.right, Term::var("Y"));
+}
+
+#[test]
+fn test_parse_rule_with_negation() {
+    let input = r#"mother(X, Y) :- parent(X, Y), !male(X), not father(X, Y), nothing(X)."#;
+    let (remaining, items) = parse_datalog(input).unwrap();
+    assert_eq!(remaining, "");
+
+    let DatalogItem::Rule(el) = &items[0] else {
+        panic!("Expected Rule variant");
+    };
+    let relations = &el.definition.relations;
+    assert_eq!(relations.len(), 4);
+
+    let DatalogItem::Negation(negated) = &relations[1] else {
+        panic!("Expected Negation");
+    };
+    let DatalogItem::Atom(fact) = negated.as_ref() else {
+        panic!("Expected negated Atom");
+    };
+    assert_eq!(fact.name, "male");
+
+    let DatalogItem::Negation(negated) = &relations[2] else {
+        panic!("Expected Negation");
+    };
+    let DatalogItem::Atom(rel) = negated.as_ref() else {
+        panic!("Expected negated Atom");
+    };
+    assert_eq!(rel.name, "father");
+
+    // A name starting with "not" is not a negation
+    let DatalogItem::Atom(fact) = &relations[3] else {
+        panic!("Expected Atom");
+    };
+    assert_eq!(fact.name, "nothing");
+}
+
+#[test]
+fn test_parse_string_values() {
+    let input = r#"person("Mary Ann", "O'Brien", "node42", "São Paulo", "say \"hi\"\n\\")."#;
+    let (remaining, atom) = parse_atom(input).unwrap();
+    assert_eq!(remaining, "");
+    assert_eq!(
+        atom.args,
+        [
+            "Mary Ann",
+            "O'Brien",
+            "node42",
+            "São Paulo",
+            "say \"hi\"\n\\"
+        ]
+        .map(Term::constant)
+    );
+    // Written back with its escapes
+    assert_eq!(atom.args[4].to_string(), r#""say \"hi\"\n\\""#);
+}
+
+#[test]
+fn test_parse_number_values() {
+    let input = r#"reading("Paris", 42, -7, 3.5, -0.25)."#;
+    let (remaining, atom) = parse_atom(input).unwrap();
+    assert_eq!(remaining, "");
+    assert_eq!(
+        atom.args,
+        [
+            Value::from("Paris"),
+            Value::Integer(42),
+            Value::Integer(-7),
+            Value::Float(3.5),
+            Value::Float(-0.25),
+        ]
+        .map(Term::Const)
+    );
+    assert_eq!(atom.to_string(), r#"reading("Paris", 42, -7, 3.5, -0.25)"#);
+
+    let (remaining, comparison) = parse_comparison("X >= 1970").unwrap();
+    assert_eq!(remaining, "");
+    assert_eq!(comparison.right, Term::constant(1970));
+}
+
+#[test]
+fn test_parse_unterminated_string_fails() {
+    assert!(parse_atom(r#"person("Mary)."#).is_err());
+    assert!(parse_atom(r#"person("Mary\")."#).is_err());
+}
+
+#[test]
+fn test_parse_spans() {
+    let input = "male(\"Bob\").\n\n  father(X, Y) :- parent(X, Y), X != \"Él\".\n?parent(X, \"Bob\"), male(X).";
+    let (remaining, items) = parse_datalog(input).unwrap();
+    assert_eq!(remaining, "");
+    let text = |span: Span| &input[span.range().expect("span is located")];
+
+    let DatalogItem::Atom(fact) = &items[0] else {
+        panic!("Expected Atom variant");
+    };
+    assert_eq!(fact.span.range(), Some(0..11));
+    assert_eq!((fact.span.line(), fact.span.column()), (1, 1));
+
+    let DatalogItem::Rule(rule) = &items[1] else {
+        panic!("Expected Rule variant");
+    };
+    assert_eq!(
+        text(rule.span),
+        "father(X, Y) :- parent(X, Y), X != \"Él\"."
+    );
+    assert_eq!((rule.span.line(), rule.span.column()), (3, 3));
+    assert_eq!(text(rule.head.span), "father(X, Y)");
+    assert_eq!(text(rule.definition.span), "parent(X, Y), X != \"Él\"");
+    let DatalogItem::Comparison(comparison) = &rule.definition.relations[1] else {
+        panic!("Expected Comparison");
+    };
+    assert_eq!(text(comparison.span), "X != \"Él\"");
+    assert_eq!((comparison.span.line(), comparison.span.column()), (3, 33));
+
+    let DatalogItem::Query(query) = &items[2] else {
+        panic!("Expected Query variant");
+    };
+    assert_eq!(text(query.span), "?parent(X, \"Bob\"), male(X).");
+    assert_eq!((query.span.line(), query.span.column()), (4, 1));
+    let NonQueryDatalogItem::ConjunctiveQuery(conjunctive) = &query.data else {
+        panic!("Expected ConjunctiveQuery");
+    };
+    let QueryProjection::Atom(male) = &conjunctive.data[1] else {
+        panic!("Expected Atom");
+    };
+    assert_eq!(text(male.span), "male(X)");
+    assert_eq!((male.span.line(), male.span.column()), (4, 20));
+}
+
+#[test]
+fn test_spans_of_other_parsers_are_located_on_demand() {
+    let input = "father(X, Y) :- parent(X, Y), male(X).";
+    let (_, mut rule) = parse_rule(input).unwrap();
+    assert!(!rule.span.is_located());
+    assert_eq!(rule.span.range(), None);
+    assert_eq!(rule.head.span.line(), 0);
+
+    rule.locate(input);
+    assert_eq!(rule.span.range(), Some(0..input.len()));
+    assert_eq!(rule.head.span.range(), Some(0..12));
+    let DatalogItem::Atom(male) = &rule.definition.relations[1] else {
+        panic!("Expected Atom");
+    };
+    assert_eq!((male.span.line(), male.span.column()), (1, 31));
+}
+
+#[test]
+fn test_spans_do_not_affect_equality() {
+    let (_, items) = parse_datalog(r#"male("Bob"). male("Bob")."#).unwrap();
+    let atoms: Vec<&Atom> = items
+        .iter()
+        .map(|item| match item {
+            DatalogItem::Atom(atom) => atom,
+            _ => panic!("Expected Atom variant"),
+        })
+        .collect();
+    assert_ne!(atoms[0].span, atoms[1].span);
+    assert_eq!(atoms[0].span.range(), Some(0..11));
+    assert_eq!(atoms[1].span.range(), Some(13..24));
+    assert_eq!(atoms[0], atoms[1]);
+
+    let atoms: HashSet<&Atom> = atoms.into_iter().collect();
+    assert_eq!(atoms.len(), 1);
+    assert!(atoms.contains(&Atom::new("male", vec![Term::constant("Bob")])));
 }
@@ -0,0 +1,139 @@
+use dataloglite::api::{Database, QueryResult};
+use dataloglite::parser::{
+    parse_query, ComparisonOperator, ConjunctiveQuery, NonQueryDatalogItem, Term,
+};
+use dataloglite::query_builder::QueryBuilder;
+use dataloglite::query_engine::Engine;
+use dataloglite::value::Value;
+
+fn parsed(query: &str) -> ConjunctiveQuery {
+    let (remaining, query) = parse_query(query).expect("Failed to parse query");
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::ConjunctiveQuery(query) = query.data else {
+        panic!("Expected NonQueryDatalogItem::ConjunctiveQuery");
+    };
+    query
+}
+
+fn family() -> Database {
+    let mut engine = Engine::new();
+    engine
+        .load(
+            r#"
+            male("Bob").
+            male("Charlie").
+            parent("Alice", "Bob").
+            parent("Alice", "Julie").
+            parent("Bob", "Charlie").
+            parent("Bob", "Cindy").
+            grandparent(X, Z) :- parent(X, Y), parent(Y, Z).
+        "#,
+        )
+        .unwrap();
+    std::mem::take(engine.database_mut())
+}
+
+#[test]
+fn test_builder_produces_the_parsed_query() {
+    let built = QueryBuilder::atom("parent")
+        .var("X")
+        .constant("Bob")
+        .and(QueryBuilder::not("male").var("X"))
+        .and(QueryBuilder::atom("born").anonymous().constant(1970))
+        .and(QueryBuilder::compare(
+            Term::var("X"),
+            ComparisonOperator::NotEqual,
+            Term::constant("Julie"),
+        ))
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        built,
+        parsed(r#"?parent(X, "Bob"), !male(X), born(_, 1970), X != "Julie"."#)
+    );
+}
+
+#[test]
+fn test_builder_runs_like_a_query_string() {
+    let mut db = family();
+
+    let built = QueryBuilder::atom("parent")
+        .var("P")
+        .var("C")
+        .and(QueryBuilder::not("male").var("C"))
+        .execute(&mut db)
+        .unwrap();
+    let text = db.query("?parent(P, C), !male(C).").unwrap();
+    assert_eq!(built, text);
+
+    // Rules are evaluated first
+    let result = QueryBuilder::atom("grandparent")
+        .constant("Alice")
+        .var("G")
+        .execute(&mut db)
+        .unwrap();
+    let QueryResult::Rows(answers) = result else {
+        panic!("Expected QueryResult::Rows");
+    };
+    assert_eq!(
+        answers.column("G").unwrap(),
+        [&Value::from("Charlie"), &Value::from("Cindy")]
+    );
+}
+
+#[test]
+fn test_builder_constants_need_no_quoting() {
+    let mut db = Database::new();
+    db.add_atom(dataloglite::parser::Atom::new(
+        "said",
+        vec![Term::constant(r#"say "hi", \o/"#)],
+    ))
+    .unwrap();
+
+    let result = QueryBuilder::atom("said")
+        .constant(r#"say "hi", \o/"#)
+        .execute(&mut db)
+        .unwrap();
+    assert_eq!(result, QueryResult::Bool(true));
+}
+
+#[test]
+fn test_builder_rejects_what_the_parser_would() {
+    let errors = [
+        (
+            QueryBuilder::atom("Parent").var("X"),
+            "`Parent` is not a predicate name",
+        ),
+        (QueryBuilder::atom("p(X)"), "`p(X)` is not a predicate name"),
+        (
+            QueryBuilder::atom("parent").var("x"),
+            "`x` is not a variable name",
+        ),
+        (
+            QueryBuilder::compare(Term::var("_"), ComparisonOperator::Equal, Term::constant(1)),
+            "`_` is not a variable name",
+        ),
+        (
+            QueryBuilder::compare(Term::var("X"), ComparisonOperator::Equal, Term::var("Y"))
+                .var("Z"),
+            "argument Z follows a comparison",
+        ),
+    ];
+    for (builder, error) in errors {
+        assert_eq!(builder.build().unwrap_err(), error);
+    }
+}
+
+#[test]
+fn test_builder_errors_from_the_database() {
+    let mut db = family();
+    let error = QueryBuilder::atom("parent")
+        .var("X")
+        .execute(&mut db)
+        .unwrap_err();
+    assert_eq!(
+        error,
+        "parent has 2 arguments, but parent(X) is used with 1"
+    );
+}
@@ -0,0 +1,459 @@
+use dataloglite::api::{Answers, Database, PreparedQuery, QueryResult};
+use dataloglite::parser::{
+    parse_datalog, parse_query, Atom, DatalogItem, NonQueryDatalogItem, Term,
+};
+use dataloglite::query_engine::Error;
+use dataloglite::value::Value;
+
+mod common;
+
+fn load(input: &str) -> Database {
+    let mut db = common::load(input);
+    db.evaluate_rules();
+    db
+}
+
+fn conjunctive(db: &Database, query: &str) -> Answers {
+    let (remaining, query) = parse_query(query).expect("Failed to parse query");
+    assert_eq!(remaining, "");
+    let NonQueryDatalogItem::ConjunctiveQuery(query) = query.data else {
+        panic!("Expected NonQueryDatalogItem::ConjunctiveQuery");
+    };
+    db.query_conjunctive(query).expect("Invalid query")
+}
+
+fn rows(rows: &[&[&str]]) -> Vec<Vec<Value>> {
+    rows.iter()
+        .map(|row| row.iter().map(|&value| Value::from(value)).collect())
+        .collect()
+}
+
+const FAMILY: &str = r#"
+    male("Bob").
+    male("Charlie").
+    parent("Alice", "Bob").
+    parent("Alice", "Julie").
+    parent("Bob", "Charlie").
+    parent("Bob", "Cindy").
+"#;
+
+#[test]
+fn test_query_returns_all_variables() {
+    let db = load(FAMILY);
+    let answers = conjunctive(&db, "?parent(X, Y), male(X).");
+
+    assert_eq!(answers.variables, ["X", "Y"]);
+    assert_eq!(
+        answers.rows,
+        rows(&[&["Bob", "Charlie"], &["Bob", "Cindy"]])
+    );
+    assert_eq!(
+        answers.column("Y").unwrap(),
+        [&Value::from("Charlie"), &Value::from("Cindy")]
+    );
+    assert_eq!(answers.column("Z"), None);
+}
+
+#[test]
+fn test_query_columns_in_first_appearance_order() {
+    let db = load(FAMILY);
+    let answers = conjunctive(&db, "?male(Child), parent(Parent, Child).");
+
+    assert_eq!(answers.variables, ["Child", "Parent"]);
+    assert_eq!(
+        answers.rows,
+        rows(&[&["Bob", "Alice"], &["Charlie", "Bob"]])
+    );
+}
+
+#[test]
+fn test_query_rows_are_distinct() {
+    let db = load(FAMILY);
+    // Alice and Bob both have two children, and there are two males
+    let answers = conjunctive(&db, "?parent(P, _), male(_).");
+
+    assert_eq!(answers.variables, ["P"]);
+    assert_eq!(answers.rows, rows(&[&["Alice"], &["Bob"]]));
+}
+
+#[test]
+fn test_query_without_variables() {
+    let db = load(FAMILY);
+
+    let answers = conjunctive(&db, "?parent(_, _), male(_).");
+    assert!(answers.variables.is_empty());
+    assert_eq!(answers.len(), 1);
+
+    let answers = conjunctive(&db, "?parent(_, _), female(_).");
+    assert!(answers.is_empty());
+}
+
+#[test]
+fn test_query_joins_on_shared_variables() {
+    let db = load(FAMILY);
+    // Grandparents: the child of the first atom is the parent of the second
+    let answers = conjunctive(&db, "?parent(X, Y), parent(Y, Z).");
+
+    assert_eq!(answers.variables, ["X", "Y", "Z"]);
+    assert_eq!(
+        answers.rows,
+        rows(&[&["Alice", "Bob", "Charlie"], &["Alice", "Bob", "Cindy"]])
+    );
+}
+
+#[test]
+fn test_query_honours_constants_in_any_position() {
+    let db = load(FAMILY);
+
+    let answers = conjunctive(&db, r#"?parent(X, "Bob"), male(X)."#);
+    assert!(answers.is_empty());
+
+    let answers = conjunctive(&db, r#"?parent(X, "Charlie"), male(X)."#);
+    assert_eq!(answers.rows, rows(&[&["Bob"]]));
+
+    let answers = conjunctive(&db, r#"?parent("Alice", C), parent(C, G)."#);
+    assert_eq!(answers.variables, ["C", "G"]);
+    assert_eq!(
+        answers.rows,
+        rows(&[&["Bob", "Charlie"], &["Bob", "Cindy"]])
+    );
+
+    let answers = conjunctive(&db, r#"?parent(P, C), !parent(P, "Cindy")."#);
+    assert_eq!(
+        answers.rows,
+        rows(&[&["Alice", "Bob"], &["Alice", "Julie"]])
+    );
+}
+
+#[test]
+fn test_query_repeated_variables() {
+    let db = load(
+        r#"
+        edge("A", "B").
+        edge("B", "C").
+        edge("C", "A").
+        edge("C", "C").
+        edge("C", "D").
+        weight("A", 1).
+        weight("B", 2).
+        weight("C", 1).
+    "#,
+    );
+
+    // The same variable twice in one atom
+    let answers = conjunctive(&db, "?edge(X, X), weight(X, W).");
+    assert_eq!(answers.variables, ["X", "W"]);
+    assert_eq!(
+        answers.rows,
+        vec![vec![Value::from("C"), Value::Integer(1)]]
+    );
+
+    // Triangles, one row per rotation, plus the self loop on C
+    let answers = conjunctive(&db, "?edge(X, Y), edge(Y, Z), edge(Z, X).");
+    assert_eq!(
+        answers.rows,
+        rows(&[
+            &["A", "B", "C"],
+            &["B", "C", "A"],
+            &["C", "A", "B"],
+            &["C", "C", "C"],
+        ])
+    );
+
+    // Joining on a numeric constant and a shared variable
+    let answers = conjunctive(&db, "?weight(X, 1), weight(Y, 1), edge(X, Y).");
+    assert_eq!(answers.rows, rows(&[&["C", "A"], &["C", "C"]]));
+}
+
+#[test]
+fn test_query_on_derived_relations() {
+    let db = load(&format!(
+        "{}{}",
+        FAMILY,
+        r#"
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+    "#
+    ));
+    let answers = conjunctive(&db, r#"?ancestor("Alice", D), male(D)."#);
+
+    assert_eq!(answers.rows, rows(&[&["Bob"], &["Charlie"]]));
+}
+
+#[test]
+fn test_query_string_ground_queries_are_booleans() {
+    let mut db = load(FAMILY);
+
+    assert_eq!(
+        db.query(r#"?parent("Alice", "Bob")."#).unwrap(),
+        QueryResult::Bool(true)
+    );
+    assert_eq!(
+        db.query(r#"?parent("Bob", "Alice")."#).unwrap(),
+        QueryResult::Bool(false)
+    );
+    // Only anonymous variables
+    assert_eq!(
+        db.query(r#"?parent(_, "Cindy"), male(_)."#).unwrap(),
+        QueryResult::Bool(true)
+    );
+    assert_eq!(db.query("?male(_).").unwrap(), QueryResult::Bool(true));
+    assert_eq!(db.query("?nobody(_).").unwrap(), QueryResult::Bool(false));
+}
+
+#[test]
+fn test_query_string_open_queries_are_rows() {
+    let mut db = load(FAMILY);
+
+    let expected = [
+        (r#"?parent(X, "Bob")."#, "X", vec!["Alice"]),
+        (r#"?parent("Bob", Y)."#, "Y", vec!["Charlie", "Cindy"]),
+        ("?parent(X, _).", "X", vec!["Alice", "Bob"]),
+        ("?parent(X, Y), male(Y).", "Y", vec!["Bob", "Charlie"]),
+    ];
+    for (query, variable, values) in expected {
+        let QueryResult::Rows(answers) = db.query(query).unwrap() else {
+            panic!("Expected rows for {}", query);
+        };
+        let values: Vec<Value> = values.into_iter().map(Value::from).collect();
+        assert_eq!(
+            answers.column(variable).unwrap(),
+            values.iter().collect::<Vec<_>>(),
+            "{}",
+            query
+        );
+    }
+}
+
+#[test]
+fn test_query_string_evaluates_rules() {
+    let mut db = Database::new();
+    db.add_atom(Atom::new(
+        "parent",
+        vec![Term::constant("Alice"), Term::constant("Bob")],
+    ))
+    .unwrap();
+    let (_, mut items) = parse_datalog("child(Y, X) :- parent(X, Y).").unwrap();
+    let Some(DatalogItem::Rule(rule)) = items.pop() else {
+        panic!("Expected Rule variant");
+    };
+    db.add_rule(rule).unwrap();
+
+    let result = db.query(r#"?child("Bob", P)."#).unwrap();
+    assert!(result.holds());
+}
+
+#[test]
+fn test_query_string_errors() {
+    let mut db = load(FAMILY);
+
+    assert!(matches!(db.query("?parent(X Y)."), Err(Error::Syntax(_))));
+    assert!(matches!(
+        db.query(r#"parent("Alice", "Bob")."#),
+        Err(Error::Statement(_))
+    ));
+
+    let Err(Error::Statement(diagnostic)) = db.query(r#"?male(X), parent(X)."#) else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "parent has 2 arguments, but parent(X) is used with 1"
+    );
+}
+
+#[test]
+fn test_projection_queries_check_arity() {
+    let mut db = load(
+        r#"
+        m("Alice", "Bob").
+        p("Alice", "Bob", "Carl").
+    "#,
+    );
+
+    let Err(Error::Statement(diagnostic)) = db.query("?m(_).") else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "m has 2 arguments, but m(_) is used with 1"
+    );
+
+    let Err(Error::Statement(diagnostic)) = db.query("?p(X, _).") else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!(
+        diagnostic.message,
+        "p has 3 arguments, but p(X, _) is used with 2"
+    );
+
+    let Err(Error::Statement(diagnostic)) = db.query(r#"?p(X, "Bob")."#) else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!(
+        diagnostic.message,
+        r#"p has 3 arguments, but p(X, "Bob") is used with 2"#
+    );
+}
+
+#[test]
+fn test_prepared_query_runs_with_each_value() {
+    let mut db = load(FAMILY);
+    let prepared = PreparedQuery::new("?parent(P, $child), !male(P).").unwrap();
+    assert_eq!(prepared.parameters(), ["child"]);
+
+    for (child, parent) in [("Bob", "Alice"), ("Julie", "Alice"), ("Cindy", "Bob")] {
+        let result = prepared
+            .execute(&mut db, &[("child", Value::from(child))])
+            .unwrap();
+        let expected = if parent == "Bob" {
+            // Bob is male
+            rows(&[])
+        } else {
+            rows(&[&[parent]])
+        };
+        let QueryResult::Rows(answers) = result else {
+            panic!("Expected QueryResult::Rows");
+        };
+        assert_eq!(answers.rows, expected, "{}", child);
+    }
+}
+
+#[test]
+fn test_prepared_query_parameters_anywhere_a_value_goes() {
+    let mut db = load(
+        r#"
+        born("Alice", 1950).
+        born("Bob", 1975).
+        born("Cindy", 2001).
+    "#,
+    );
+    let prepared = PreparedQuery::new("?born(X, Y), Y >= $from, Y < $to.").unwrap();
+    assert_eq!(prepared.parameters(), ["from", "to"]);
+
+    let result = prepared
+        .execute(
+            &mut db,
+            &[("to", Value::Integer(2000)), ("from", Value::Integer(1960))],
+        )
+        .unwrap();
+    let QueryResult::Rows(answers) = result else {
+        panic!("Expected QueryResult::Rows");
+    };
+    assert_eq!(answers.rows, [[Value::from("Bob"), Value::Integer(1975)]]);
+
+    // A ground query once bound
+    let prepared = PreparedQuery::new("?born($name, $year).").unwrap();
+    let result = prepared.execute(
+        &mut db,
+        &[
+            ("name", Value::from("Cindy")),
+            ("year", Value::Integer(2001)),
+        ],
+    );
+    assert_eq!(result, Ok(QueryResult::Bool(true)));
+}
+
+#[test]
+fn test_prepared_query_errors() {
+    let mut db = load(FAMILY);
+    let prepared = PreparedQuery::new("?parent(P, $child).").unwrap();
+
+    assert_eq!(
+        prepared.execute(&mut db, &[]),
+        Err("parameter $child has no value".to_string())
+    );
+    assert_eq!(
+        prepared.execute(
+            &mut db,
+            &[("child", Value::from("Bob")), ("kid", Value::from("Bob"))]
+        ),
+        Err("the query has no parameter $kid".to_string())
+    );
+
+    // Without preparing, a parameter has no value
+    let Err(Error::Statement(diagnostic)) = db.query("?parent(P, $child).") else {
+        panic!("Expected Error::Statement");
+    };
+    assert_eq!(diagnostic.message, "parameter $child has no value");
+}
+
+#[test]
+fn test_parameters_only_in_queries() {
+    let mut db = Database::new();
+    let (_, items) = parse_datalog("parent($p, \"Bob\"). child(X) :- parent($p, X).").unwrap();
+    let mut items = items.into_iter();
+
+    let Some(DatalogItem::Atom(fact)) = items.next() else {
+        panic!("Expected Atom variant");
+    };
+    assert_eq!(fact.args[0], Term::param("p"));
+    assert_eq!(
+        db.add_atom(fact).unwrap_err(),
+        r#"parameter $p in fact `parent($p, "Bob")` can only be used in a query"#
+    );
+
+    let Some(DatalogItem::Rule(rule)) = items.next() else {
+        panic!("Expected Rule variant");
+    };
+    assert_eq!(
+        db.add_rule(rule).unwrap_err(),
+        "parameter $p in rule `child(X)` can only be used in a query"
+    );
+}
+
+#[test]
+fn test_query_iter_yields_distinct_rows() {
+    let mut db = load(FAMILY);
+
+    let answers = db.query_iter("?parent(P, _), male(_).").unwrap();
+    assert_eq!(answers.variables(), ["P"]);
+    let mut parents: Vec<Vec<Value>> = answers.collect();
+    parents.sort();
+    assert_eq!(parents, rows(&[&["Alice"], &["Bob"]]));
+
+    // Sorting is asked for explicitly and gives the same answers as `query`
+    let sorted = db.query_iter("?parent(X, Y), male(Y).").unwrap().sorted();
+    assert_eq!(
+        QueryResult::Rows(sorted),
+        db.query("?parent(X, Y), male(Y).").unwrap()
+    );
+}
+
+#[test]
+fn test_query_iter_stops_early() {
+    let mut db = Database::new();
+    db.add_atoms((0..2000).map(|i| Atom::new("n", vec![Term::constant(i as i64)])))
+        .unwrap();
+
+    // 8 billion combinations, of which only the first few are searched for
+    let first: Vec<Vec<Value>> = db
+        .query_iter("?n(X), n(Y), n(Z), X != Y.")
+        .unwrap()
+        .take(5)
+        .collect();
+    assert_eq!(first.len(), 5);
+    for row in &first {
+        assert_ne!(row[0], row[1]);
+    }
+}
+
+#[test]
+fn test_query_iter_every_kind_of_query() {
+    let mut db = load(FAMILY);
+    let expected = [
+        ("?male(_).", vec!["Bob", "Charlie"]),
+        ("?parent(_, C).", vec!["Bob", "Charlie", "Cindy", "Julie"]),
+        (r#"?parent(P, "Julie")."#, vec!["Alice"]),
+        (r#"?parent("Bob", C)."#, vec!["Charlie", "Cindy"]),
+    ];
+    for (query, values) in expected {
+        let mut column: Vec<Value> = db.query_iter(query).unwrap().flatten().collect();
+        column.sort();
+        let values: Vec<Value> = values.into_iter().map(Value::from).collect();
+        assert_eq!(column, values, "{}", query);
+    }
+
+    let answers: Vec<_> = db.query_iter(r#"?male("Bob")."#).unwrap().collect();
+    assert_eq!(answers, [Vec::<Value>::new()]);
+}
@@ -0,0 +1,160 @@
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+use dataloglite::api::Database;
+use dataloglite::datalog;
+use dataloglite::snapshot::VERSION;
+
+fn family() -> Database {
+    datalog! {
+        parent("Alice", "Bob").
+        parent("Bob", "Carl").
+        male("Bob").
+        born("Alice", 1970).
+        height("Alice", 1.62).
+        raining.
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+        mother(X, Y) :- parent(X, Y), !male(X).
+        tall(X) :- height(X, H), H >= 1.6.
+    }
+}
+
+fn load_error(bytes: &[u8]) -> io::Error {
+    match Database::from_snapshot(bytes) {
+        Ok(_) => panic!("Expected an invalid snapshot"),
+        Err(error) => error,
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dataloglite-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    let mut db = family();
+    db.evaluate_rules();
+    let path = temp_path("round-trip.snapshot");
+    db.save_snapshot(&path).unwrap();
+    let mut loaded = Database::load_snapshot(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.atoms(), db.atoms());
+    assert_eq!(loaded.derived_atoms(), db.derived_atoms());
+    assert_eq!(loaded.rules().len(), 4);
+    assert_eq!(loaded.arity("born"), Some(2));
+    assert_eq!(loaded.symbols().len(), db.symbols().len());
+
+    let rows: Vec<(String,)> = loaded.query_as(r#"?ancestor(X, "Carl")."#).unwrap();
+    assert_eq!(rows, [("Alice".to_string(),), ("Bob".to_string(),)]);
+    assert!(loaded.query("?raining.").unwrap().holds());
+    assert!(loaded.query(r#"?tall("Alice")."#).unwrap().holds());
+
+    // The rules still apply to facts added after loading
+    loaded.insert(("parent", "Carl", "Dave")).unwrap();
+    let rows: Vec<(String,)> = loaded.query_as(r#"?ancestor("Alice", X)."#).unwrap();
+    assert_eq!(rows.len(), 3);
+    let rows: Vec<(String, String)> = loaded.query_as("?mother(X, Y).").unwrap();
+    assert_eq!(
+        rows,
+        [
+            ("Alice".to_string(), "Bob".to_string()),
+            ("Carl".to_string(), "Dave".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_snapshot_is_replaced_only_when_complete() {
+    let path = temp_path("replaced.snapshot");
+    let temporary = temp_path("replaced.snapshot.tmp");
+    let mut db = family();
+    db.save_snapshot(&path).unwrap();
+    assert!(!temporary.exists());
+
+    // The new snapshot cannot be written, so the old one is left as it was
+    std::fs::create_dir(&temporary).unwrap();
+    db.insert(("parent", "Carl", "Dave")).unwrap();
+    assert!(db.save_snapshot(&path).is_err());
+    std::fs::remove_dir(&temporary).unwrap();
+    let loaded = Database::load_snapshot(&path).unwrap();
+    assert_eq!(loaded.atoms(), family().atoms());
+
+    db.save_snapshot(&path).unwrap();
+    let loaded = Database::load_snapshot(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.atoms(), db.atoms());
+    assert!(!temporary.exists());
+}
+
+#[test]
+fn test_snapshot_of_unevaluated_database() {
+    let db = family();
+    let mut loaded = Database::from_snapshot(&db.to_snapshot()).unwrap();
+    assert!(loaded.derived_atoms().is_empty());
+    assert!(loaded
+        .query(r#"?ancestor("Alice", "Carl")."#)
+        .unwrap()
+        .holds());
+    assert_eq!(Database::new().to_snapshot().len(), 57);
+}
+
+#[test]
+fn test_snapshot_is_deterministic() {
+    let mut first = family();
+    let mut second = family();
+    first.evaluate_rules();
+    second.evaluate_rules();
+    assert_eq!(first.to_snapshot(), second.to_snapshot());
+}
+
+#[test]
+fn test_snapshot_rejects_other_files() {
+    let error = load_error(b"parent(\"Alice\", \"Bob\").");
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(error.to_string(), "not a dataloglite snapshot");
+
+    let error = load_error(b"");
+    assert_eq!(error.to_string(), "not a dataloglite snapshot");
+
+    let result = Database::load_snapshot(temp_path("missing.snapshot"));
+    assert!(matches!(result, Err(error) if error.kind() == ErrorKind::NotFound));
+}
+
+#[test]
+fn test_snapshot_rejects_other_versions() {
+    let mut bytes = family().to_snapshot();
+    bytes[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    let error = load_error(&bytes);
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "snapshot format version {} is not supported, expected {}",
+            VERSION + 1,
+            VERSION
+        )
+    );
+}
+
+#[test]
+fn test_snapshot_detects_damage() {
+    let bytes = family().to_snapshot();
+
+    let mut damaged = bytes.clone();
+    let middle = damaged.len() / 2;
+    damaged[middle] ^= 0x01;
+    let error = load_error(&damaged);
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert_eq!(
+        error.to_string(),
+        "snapshot checksum mismatch, the file is damaged"
+    );
+
+    let error = load_error(&bytes[..bytes.len() - 10]);
+    assert_eq!(
+        error.to_string(),
+        "snapshot checksum mismatch, the file is damaged"
+    );
+}
@@ -0,0 +1,537 @@
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use dataloglite::api::Database;
+use dataloglite::datalog;
+use dataloglite::journal::JournaledDatabase;
+use dataloglite::parser::{parse_value, Atom};
+use dataloglite::query_engine::Engine;
+use dataloglite::storage::{AtomSet, Atoms, Storage, Symbol, Symbols, Tuples};
+use dataloglite::value::Value;
+
+mod common;
+
+use common::atom;
+
+#[test]
+fn test_symbols_keep_each_value_once() {
+    let mut symbols = Symbols::new();
+    let alice = symbols.intern(&Value::from("Alice"));
+    assert_eq!(symbols.intern(&Value::from("Alice")), alice);
+    let one = symbols.intern(&Value::Integer(1));
+    // Integers and floats are different values
+    assert_ne!(symbols.intern(&Value::Float(1.0)), one);
+
+    assert_eq!(symbols.len(), 3);
+    assert_eq!(symbols.resolve(alice), &Value::from("Alice"));
+    assert_eq!(symbols.get(&Value::from("Bob")), None);
+    assert_eq!(
+        symbols.resolve_atom("age", &[alice, one]).to_string(),
+        r#"age("Alice", 1)"#
+    );
+}
+
+#[test]
+fn test_atom_set_partitions_by_predicate() {
+    let mut symbols = Symbols::new();
+    let mut set = AtomSet::new();
+    for atom in [
+        atom("parent", &["Alice", "Bob"]),
+        atom("parent", &["Bob", "Carl"]),
+        atom("male", &["Bob"]),
+        atom("male", &["Bob", "twice"]),
+    ] {
+        assert!(set.insert(&atom.name, &symbols.intern_atom(&atom)));
+    }
+    let bob = symbols.get(&Value::from("Bob")).unwrap();
+    assert!(!set.insert("male", &[bob]));
+
+    assert_eq!(set.len(), 4);
+    let atoms = Atoms::new(&set, &symbols);
+    assert!(atoms.contains(&atom("male", &["Bob"])));
+    assert!(!atoms.contains(&atom("male", &["Carl"])));
+    assert!(!atoms.contains(&atom("male", &["Dave"])));
+    assert_eq!(atoms.tuples_of("parent", 2).count(), 2);
+    assert_eq!(atoms.tuples_of("male", 1).count(), 1);
+    assert_eq!(atoms.tuples_of("female", 1).count(), 0);
+}
+
+#[test]
+fn test_many_tuples_are_found_and_removed() {
+    let mut symbols = Symbols::new();
+    let mut set = AtomSet::new();
+    let ids: Vec<Symbol> = (0..1000)
+        .map(|i| symbols.intern(&Value::Integer(i)))
+        .collect();
+    assert_eq!(symbols.len(), 1000);
+    for (i, &id) in ids.iter().enumerate() {
+        assert_eq!(symbols.get(&Value::Integer(i as i64)), Some(id));
+        assert!(set.insert("next", &[id, ids[(i + 1) % 1000]]));
+    }
+    assert!(!set.insert("next", &[ids[999], ids[0]]));
+    assert_eq!(set.count("next", 2), 1000);
+    // Both columns are indexed, and kept up to date by the removals
+    assert_eq!(set.lookup("next", 2, &[(0, ids[3])]).count(), 1);
+    assert_eq!(set.lookup("next", 2, &[(1, ids[3])]).count(), 1);
+
+    for i in (0..1000).step_by(2) {
+        assert!(set.remove("next", &[ids[i], ids[i + 1]]));
+        assert!(!set.remove("next", &[ids[i], ids[i + 1]]));
+    }
+    assert_eq!(set.count("next", 2), 500);
+    for i in 0..999 {
+        assert_eq!(set.contains("next", &[ids[i], ids[i + 1]]), i % 2 == 1);
+        let after: Vec<&[Symbol]> = set.lookup("next", 2, &[(0, ids[i])]).collect();
+        let before: Vec<&[Symbol]> = set.lookup("next", 2, &[(1, ids[i + 1])]).collect();
+        assert_eq!(after, before);
+        assert_eq!(after.len(), i % 2);
+    }
+    assert!(set.is_indexed("next", 2, 0) && set.is_indexed("next", 2, 1));
+    assert!(set.insert("next", &[ids[0], ids[1]]));
+    assert!(set.contains("next", &[ids[0], ids[1]]));
+}
+
+#[test]
+fn test_columns_are_indexed_on_first_lookup() {
+    let mut db = Database::new();
+    db.insert_all([
+        ("parent", "Alice", "Bob"),
+        ("parent", "Alice", "Julie"),
+        ("parent", "Bob", "Carl"),
+    ])
+    .unwrap();
+    assert!(!db.atoms().is_indexed("parent", 2, 0));
+
+    let children: Vec<String> = db
+        .relations_where_first_is("parent", &Value::from("Alice"))
+        .iter()
+        .map(Atom::to_string)
+        .collect();
+    assert_eq!(
+        children,
+        [r#"parent("Alice", "Bob")"#, r#"parent("Alice", "Julie")"#]
+    );
+    assert!(db.atoms().is_indexed("parent", 2, 0));
+    assert!(!db.atoms().is_indexed("parent", 2, 1));
+
+    // Atoms added later are indexed too
+    db.insert(("parent", "Alice", "Dave")).unwrap();
+    let alice = Value::from("Alice");
+    assert_eq!(db.tuples_where("parent", 2, 0, &alice).count(), 3);
+    assert_eq!(
+        db.tuples_where("parent", 2, 1, &Value::from("Nobody"))
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_queries_and_rules_index_bound_columns() {
+    let mut db = Database::new();
+    db.insert_all([
+        ("parent", "Alice", "Bob"),
+        ("parent", "Bob", "Carl"),
+        ("parent", "Carl", "Dave"),
+    ])
+    .unwrap();
+
+    let result = db.query(r#"?parent(P, "Carl")."#).unwrap();
+    assert!(result.holds());
+    assert!(db.atoms().is_indexed("parent", 2, 1));
+    assert!(!db.atoms().is_indexed("parent", 2, 0));
+
+    let mut db = datalog! {
+        parent("Alice", "Bob").
+        parent("Bob", "Carl").
+        parent("Carl", "Dave").
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+    };
+    let rows: Vec<(String,)> = db.query_as(r#"?ancestor(X, "Dave")."#).unwrap();
+    assert_eq!(rows.len(), 3);
+    assert!(db.derived_atoms().is_indexed("ancestor", 2, 1));
+}
+
+#[test]
+fn test_values_are_shared_by_atoms() {
+    let mut db = datalog! {
+        parent("Alice", "Bob").
+        parent("Alice", "Julie").
+        parent("Bob", "Carl").
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+    };
+    assert_eq!(db.symbols().len(), 4);
+
+    // Derived atoms reuse the symbols of the values they are made of
+    db.evaluate_rules();
+    assert_eq!(db.derived_atoms().len(), 4);
+    assert_eq!(db.symbols().len(), 4);
+
+    let mut atoms: Vec<String> = db.atoms_of("ancestor", 2).map(|a| a.to_string()).collect();
+    atoms.sort();
+    assert_eq!(atoms[0], r#"ancestor("Alice", "Bob")"#);
+}
+
+#[test]
+fn test_retract_removes_facts_and_derived_relations() {
+    let mut db = datalog! {
+        parent("Alice", "Bob").
+        parent("Alice", "Julie").
+        parent("Bob", "Carl").
+        grandparent(X, Z) :- parent(X, Y), parent(Y, Z).
+    };
+    assert_eq!(
+        db.tuples_where("parent", 2, 0, &Value::from("Alice"))
+            .count(),
+        2
+    );
+
+    assert!(db.retract(("parent", "Alice", "Julie")));
+    assert!(!db.retract(("parent", "Alice", "Julie")));
+    assert!(!db.retract(("parent", "Nobody", "Julie")));
+    assert_eq!(db.atoms().len(), 2);
+    // The index is built again without the removed tuple
+    let alice = Value::from("Alice");
+    assert_eq!(db.tuples_where("parent", 2, 0, &alice).count(), 1);
+
+    assert!(db
+        .query(r#"?grandparent("Alice", "Carl")."#)
+        .unwrap()
+        .holds());
+    assert!(db.retract(("parent", "Bob", "Carl")));
+    assert!(!db
+        .query(r#"?grandparent("Alice", "Carl")."#)
+        .unwrap()
+        .holds());
+
+    assert!(db.retract(("parent", "Alice", "Bob")));
+    assert_eq!(db.atoms().count("parent", 2), 0);
+    assert!(db.atoms().is_empty());
+}
+
+/// A backend keeping tuples sorted by predicate, without indexes
+#[derive(Default)]
+struct SortedStorage {
+    tuples: BTreeSet<(String, Vec<Symbol>)>,
+}
+
+impl Storage for SortedStorage {
+    fn insert(&mut self, name: &str, tuple: &[Symbol]) -> bool {
+        self.tuples.insert((name.to_string(), tuple.to_vec()))
+    }
+
+    fn remove(&mut self, name: &str, tuple: &[Symbol]) -> bool {
+        self.tuples.remove(&(name.to_string(), tuple.to_vec()))
+    }
+
+    fn contains(&self, name: &str, tuple: &[Symbol]) -> bool {
+        self.tuples.contains(&(name.to_string(), tuple.to_vec()))
+    }
+
+    fn scan(&self, name: &str, arity: usize) -> Tuples<'_> {
+        let name = name.to_string();
+        Box::new(
+            self.tuples
+                .range((name.clone(), Vec::new())..)
+                .take_while(move |(other, _)| *other == name)
+                .filter(move |(_, tuple)| tuple.len() == arity)
+                .map(|(_, tuple)| tuple.as_slice()),
+        )
+    }
+
+    fn lookup(&self, name: &str, arity: usize, bound: &[(usize, Symbol)]) -> Tuples<'_> {
+        let bound = bound.to_vec();
+        Box::new(self.scan(name, arity).filter(move |tuple| {
+            bound
+                .iter()
+                .all(|&(column, symbol)| tuple[column] == symbol)
+        }))
+    }
+
+    fn count(&self, name: &str, arity: usize) -> usize {
+        self.scan(name, arity).count()
+    }
+
+    fn len(&self) -> usize {
+        self.tuples.len()
+    }
+
+    fn clear(&mut self) {
+        self.tuples.clear();
+    }
+
+    fn predicates(&self) -> Box<dyn Iterator<Item = (&str, usize)> + '_> {
+        let predicates: BTreeSet<(&str, usize)> = self
+            .tuples
+            .iter()
+            .map(|(name, tuple)| (name.as_str(), tuple.len()))
+            .collect();
+        Box::new(predicates.into_iter())
+    }
+
+    fn empty(&self) -> Box<dyn Storage> {
+        Box::new(SortedStorage::default())
+    }
+}
+
+#[test]
+fn test_databases_run_on_any_storage() {
+    let program = r#"
+        parent("Alice", "Bob").
+        parent("Alice", "Julie").
+        parent("Bob", "Carl").
+        parent("Carl", "Dave").
+        male("Bob").
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+        mother(X, Y) :- parent(X, Y), !male(X).
+    "#;
+    let mut memory = Engine::new();
+    memory.load(program).unwrap();
+    let mut sorted = Engine::with_database(Database::with_storage(SortedStorage::default()));
+    sorted.load(program).unwrap();
+
+    for query in [
+        r#"?ancestor("Alice", X)."#,
+        r#"?ancestor(X, "Dave"), parent(X, "Dave")."#,
+        "?mother(X, Y).",
+        r#"?parent(X, _), !ancestor(X, "Dave")."#,
+    ] {
+        assert_eq!(sorted.query(query).unwrap(), memory.query(query).unwrap());
+    }
+    let db = sorted.database_mut();
+    assert_eq!(db.derived_atoms().count("ancestor", 2), 7);
+    assert!(!db.atoms().is_indexed("parent", 2, 0));
+
+    // Snapshots do not depend on the storage they are taken from
+    assert_eq!(db.to_snapshot(), memory.database_mut().to_snapshot());
+
+    assert!(db.retract(("parent", "Bob", "Carl")));
+    let rows: Vec<(String,)> = db.query_as(r#"?ancestor("Alice", X)."#).unwrap();
+    assert_eq!(rows, [("Bob".to_string(),), ("Julie".to_string(),)]);
+}
+
+#[test]
+fn test_snapshots_load_into_any_storage() {
+    let mut memory = datalog! {
+        parent("Alice", "Bob").
+        parent("Bob", "Carl").
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+    };
+    memory.evaluate_rules();
+    let bytes = memory.to_snapshot();
+
+    let mut db = Database::from_snapshot_with(&bytes, SortedStorage::default()).unwrap();
+    assert_eq!(db.atoms(), memory.atoms());
+    assert_eq!(db.derived_atoms(), memory.derived_atoms());
+    let rows: Vec<(String,)> = db.query_as(r#"?ancestor("Alice", X)."#).unwrap();
+    assert_eq!(rows, [("Bob".to_string(),), ("Carl".to_string(),)]);
+    // An in-memory set would have indexed the columns looked up
+    assert!(!db.derived_atoms().is_indexed("ancestor", 2, 0));
+
+    let directory = std::env::temp_dir().join(format!(
+        "dataloglite-storage-{}-journal",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    let (snapshot, journal) = (directory.join("snapshot"), directory.join("journal"));
+    memory.save_snapshot(&snapshot).unwrap();
+    let mut db =
+        JournaledDatabase::open_with(&snapshot, &journal, SortedStorage::default()).unwrap();
+    db.insert(("parent", "Carl", "Dave")).unwrap();
+    drop(db);
+    let mut db =
+        JournaledDatabase::open_with(&snapshot, &journal, SortedStorage::default()).unwrap();
+    let rows: Vec<(String,)> = db.query_as(r#"?ancestor("Alice", X)."#).unwrap();
+    assert_eq!(rows.len(), 3);
+    assert!(!db.database().atoms().is_indexed("parent", 2, 0));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn test_databases_can_move_between_threads() {
+    fn send_and_sync<T: Send + Sync>() {}
+    send_and_sync::<Database>();
+    send_and_sync::<Engine>();
+    send_and_sync::<JournaledDatabase>();
+
+    let db = std::sync::Mutex::new(Database::with_storage(SortedStorage::default()));
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            db.lock()
+                .unwrap()
+                .insert(("parent", "Alice", "Bob"))
+                .unwrap()
+        });
+    });
+    assert_eq!(db.into_inner().unwrap().atoms().len(), 1);
+}
+
+/// A backend appending its changes to a file, replayed when it is opened
+struct DiskStorage {
+    file: File,
+    symbols: Symbols,
+    tuples: SortedStorage,
+}
+
+impl DiskStorage {
+    fn open(path: &Path) -> Self {
+        let mut storage = DiskStorage {
+            file: OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap(),
+            symbols: Symbols::new(),
+            tuples: SortedStorage::default(),
+        };
+        for line in std::fs::read_to_string(path).unwrap().lines() {
+            let mut fields = line.split('\t');
+            let change = fields.next().unwrap();
+            let values: Vec<Value> = fields.map(|field| parse_value(field).unwrap().1).collect();
+            match change {
+                "value" => {
+                    storage.symbols.intern(&values[0]);
+                }
+                "insert" | "remove" => {
+                    let name = values[0].to_string();
+                    let tuple: Vec<Symbol> = values[1..]
+                        .iter()
+                        .map(|value| storage.symbols.get(value).unwrap())
+                        .collect();
+                    if change == "insert" {
+                        storage.tuples.insert(&name, &tuple);
+                    } else {
+                        storage.tuples.remove(&name, &tuple);
+                    }
+                }
+                _ => storage.forget(),
+            }
+        }
+        storage
+    }
+
+    fn record(&mut self, change: &str, name: &str, tuple: &[Symbol]) {
+        let name = Value::from(name);
+        let mut values = vec![&name];
+        values.extend(tuple.iter().map(|&symbol| self.symbols.resolve(symbol)));
+        let line: Vec<String> = values.iter().map(|value| value.to_source()).collect();
+        writeln!(self.file, "{}\t{}", change, line.join("\t")).unwrap();
+    }
+
+    fn forget(&mut self) {
+        self.symbols = Symbols::new();
+        self.tuples.clear();
+    }
+}
+
+impl Storage for DiskStorage {
+    fn insert(&mut self, name: &str, tuple: &[Symbol]) -> bool {
+        let inserted = self.tuples.insert(name, tuple);
+        if inserted {
+            self.record("insert", name, tuple);
+        }
+        inserted
+    }
+
+    fn remove(&mut self, name: &str, tuple: &[Symbol]) -> bool {
+        let removed = self.tuples.remove(name, tuple);
+        if removed {
+            self.record("remove", name, tuple);
+        }
+        removed
+    }
+
+    fn contains(&self, name: &str, tuple: &[Symbol]) -> bool {
+        self.tuples.contains(name, tuple)
+    }
+
+    fn scan(&self, name: &str, arity: usize) -> Tuples<'_> {
+        self.tuples.scan(name, arity)
+    }
+
+    fn lookup(&self, name: &str, arity: usize, bound: &[(usize, Symbol)]) -> Tuples<'_> {
+        self.tuples.lookup(name, arity, bound)
+    }
+
+    fn count(&self, name: &str, arity: usize) -> usize {
+        self.tuples.count(name, arity)
+    }
+
+    fn len(&self) -> usize {
+        self.tuples.len()
+    }
+
+    fn clear(&mut self) {
+        self.forget();
+        writeln!(self.file, "clear").unwrap();
+    }
+
+    fn add_value(&mut self, symbol: Symbol, value: &Value) {
+        assert_eq!(self.symbols.intern(value), symbol);
+        writeln!(self.file, "value\t{}", value.to_source()).unwrap();
+    }
+
+    fn values(&self) -> Vec<Value> {
+        self.symbols
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn predicates(&self) -> Box<dyn Iterator<Item = (&str, usize)> + '_> {
+        self.tuples.predicates()
+    }
+
+    fn empty(&self) -> Box<dyn Storage> {
+        Box::new(SortedStorage::default())
+    }
+}
+
+#[test]
+fn test_databases_reopen_on_storages_keeping_values() {
+    let path = std::env::temp_dir().join(format!("dataloglite-{}-disk", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let rules = "
+        ancestor(X, Y) :- parent(X, Y).
+        ancestor(X, Y) :- parent(X, Z), ancestor(Z, Y).
+    ";
+
+    let mut engine = Engine::with_database(Database::with_storage(DiskStorage::open(&path)));
+    engine
+        .load(r#"parent("Alice", "Bob"). parent("Bob", "Carl"). age("Alice", 30)."#)
+        .unwrap();
+    engine.load(rules).unwrap();
+    engine.query("?ancestor(X, Y).").unwrap();
+    let db = engine.database_mut();
+    assert!(db.retract(("parent", "Bob", "Carl")));
+    db.insert(("parent", "Bob", "Dave")).unwrap();
+    drop(engine);
+
+    // Facts come back with their values, rules are loaded again
+    let mut engine = Engine::with_database(Database::with_storage(DiskStorage::open(&path)));
+    assert_eq!(engine.database().atoms().len(), 3);
+    assert!(engine.load(r#"age("Bob")."#).is_err());
+    engine.load(rules).unwrap();
+    let db = engine.database_mut();
+    let rows: Vec<(String,)> = db.query_as(r#"?ancestor("Alice", X)."#).unwrap();
+    assert_eq!(rows, [("Bob".to_string(),), ("Dave".to_string(),)]);
+    let rows: Vec<(i64,)> = db.query_as(r#"?age("Alice", X)."#).unwrap();
+    assert_eq!(rows, [(30,)]);
+    db.insert(("parent", "Dave", "Eve")).unwrap();
+    drop(engine);
+
+    let mut db = Database::with_storage(DiskStorage::open(&path));
+    assert!(db.contains_atom(&atom("parent", &["Dave", "Eve"])));
+    db.clear();
+    drop(db);
+    assert!(Database::with_storage(DiskStorage::open(&path))
+        .atoms()
+        .is_empty());
+    std::fs::remove_file(&path).unwrap();
+}